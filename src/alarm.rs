@@ -0,0 +1,242 @@
+use crate::bell::{BellKind, Cue};
+use crate::noise::SAMPLE_RATE;
+use crate::volume::Volume;
+use chrono::{NaiveDateTime, NaiveTime, TimeDelta};
+use rodio::Source;
+use rodio::source::UniformSourceIterator;
+use serde::Deserialize;
+use std::time::Duration;
+
+// The wake-up sound is converted to this format so the chime can be mixed in
+const CHANNELS: u16 = 2;
+// An alarm found this late, e.g. after the computer slept through it, is
+// skipped rather than going off at the wrong time
+const MISSED_AFTER: TimeDelta = TimeDelta::minutes(10);
+
+// The wake-up alarm, from the config's [alarm] table
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct AlarmConfig {
+    // Local time like "07:30"; there's no alarm when unset
+    pub time: Option<String>,
+    // A built-in sound by name, like "ocean"; the selected sound when unset
+    pub sound: Option<String>,
+    // How long the sound takes to rise from silence to `volume`
+    pub fade_minutes: f32,
+    // Percent, on the same curve as the main volume
+    pub volume: u8,
+    // Struck once the fade is complete
+    pub chime: Option<BellKind>,
+}
+
+impl Default for AlarmConfig {
+    fn default() -> Self {
+        AlarmConfig {
+            time: None,
+            sound: None,
+            fade_minutes: 15.0,
+            volume: 60,
+            chime: None,
+        }
+    }
+}
+
+impl AlarmConfig {
+    pub fn time(&self) -> Result<Option<NaiveTime>, String> {
+        match &self.time {
+            Some(time) => NaiveTime::parse_from_str(time, "%H:%M")
+                .map(Some)
+                .map_err(|_| format!("alarm time \"{}\" should look like \"07:30\"", time)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn fade(&self) -> Result<Duration, String> {
+        Duration::try_from_secs_f32(self.fade_minutes.max(0.0) * 60.0)
+            .map_err(|_| format!("alarm fade_minutes = {} is out of range", self.fade_minutes))
+    }
+}
+
+// Goes off when the wall clock passes the alarm time. Compared with the
+// clock on every check rather than counted down, so it's still right after
+// the clock is changed, daylight saving starts or ends, or the computer
+// wakes up.
+pub struct Alarm {
+    time: NaiveTime,
+    // The clock at the previous check
+    last: NaiveDateTime,
+    // When it last went off, so setting the clock back doesn't repeat it
+    fired: Option<NaiveDateTime>,
+}
+
+impl Alarm {
+    pub fn new(time: NaiveTime, now: NaiveDateTime) -> Self {
+        Alarm {
+            time,
+            last: now,
+            fired: None,
+        }
+    }
+
+    pub fn time(&self) -> NaiveTime {
+        self.time
+    }
+
+    // True once each time the alarm time is reached, given the local time
+    pub fn due(&mut self, now: NaiveDateTime) -> bool {
+        // The most recent alarm time, today's or yesterday's
+        let mut alarm = now.date().and_time(self.time);
+        if alarm > now {
+            alarm -= TimeDelta::days(1);
+        }
+        let passed = self.last < alarm;
+        self.last = now;
+        if !passed || self.fired == Some(alarm) {
+            return false;
+        }
+        if now - alarm > MISSED_AFTER {
+            println!("Skipped the wake-up alarm, it was due {} minutes ago", (now - alarm).num_minutes());
+            return false;
+        }
+        self.fired = Some(alarm);
+        true
+    }
+}
+
+// Fades a sound in from silence to full level along the volume curve, so it
+// rises by the same number of decibels every second, then strikes the chime
+// on the exact sample the fade ends. The sink is set to the alarm's volume
+// and this supplies the gain below it.
+pub struct WakeUp {
+    source: UniformSourceIterator<Box<dyn Source<Item = f32> + Send>>,
+    target: Volume,
+    fade_frames: u64,
+    frame: u64,
+    chime: Option<Cue>,
+    ringing: Option<Cue>,
+    // Gain and chime sample for the current frame
+    gain: f32,
+    chime_sample: f32,
+    channel: u16,
+}
+
+impl WakeUp {
+    pub fn new(source: Box<dyn Source<Item = f32> + Send>, fade: Duration, target: Volume, chime: Option<Cue>) -> Self {
+        WakeUp {
+            source: UniformSourceIterator::new(source, CHANNELS, SAMPLE_RATE),
+            target,
+            fade_frames: (fade.as_secs_f64() * SAMPLE_RATE as f64).round() as u64,
+            frame: 0,
+            chime,
+            ringing: None,
+            gain: 0.0,
+            chime_sample: 0.0,
+            channel: 0,
+        }
+    }
+
+    fn start_frame(&mut self) {
+        self.gain = if self.frame < self.fade_frames && self.target.percent() > 0 {
+            let percent = self.target.percent() as f32 * self.frame as f32 / self.fade_frames as f32;
+            Volume::amplitude_at(percent) / self.target.amplitude()
+        } else {
+            1.0
+        };
+        if self.frame == self.fade_frames {
+            self.ringing = self.chime.take();
+        }
+        self.chime_sample = self.ringing.as_mut().and_then(Iterator::next).unwrap_or(0.0);
+        self.frame += 1;
+    }
+}
+
+impl Iterator for WakeUp {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.channel == 0 {
+            self.start_frame();
+        }
+        self.channel = (self.channel + 1) % CHANNELS;
+        let sample = self.source.next()?;
+        Some(sample * self.gain + self.chime_sample)
+    }
+}
+
+impl Source for WakeUp {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        CHANNELS
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use rodio::source::Zero;
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 3, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn goes_off_once_a_day_by_the_wall_clock() {
+        let time = NaiveTime::from_hms_opt(7, 30, 0).unwrap();
+        let mut alarm = Alarm::new(time, at(1, 22, 0));
+        assert!(!alarm.due(at(2, 7, 29)));
+        assert!(alarm.due(at(2, 7, 30)));
+        assert!(!alarm.due(at(2, 7, 31)));
+
+        // Set back an hour after it went off: not again today
+        assert!(!alarm.due(at(2, 6, 45)));
+        assert!(alarm.due(at(3, 7, 31)));
+
+        // Asleep through the alarm: skipped, not sounded hours late
+        assert!(!alarm.due(at(4, 11, 0)));
+        assert!(alarm.due(at(5, 7, 30)));
+
+        // Set back a week: still rings the next morning
+        assert!(!alarm.due(at(1, 8, 0)));
+        assert!(alarm.due(at(2, 7, 30)));
+    }
+
+    #[test]
+    fn fades_in_from_silence_then_chimes() {
+        let source: Box<dyn Source<Item = f32> + Send> = Box::new(rodio::source::SineWave::new(1.0));
+        let fade = Duration::from_millis(500);
+        let mut wake_up = WakeUp::new(source, fade, Volume::from_percent(60), None);
+        let fade_frames = SAMPLE_RATE as usize / 2;
+        let gains: Vec<f32> = (0..fade_frames + 10)
+            .map(|_| {
+                wake_up.by_ref().take(2).for_each(drop);
+                wake_up.gain
+            })
+            .collect();
+        assert_eq!(gains[0], 0.0);
+        assert!(gains.windows(2).all(|pair| pair[1] >= pair[0]));
+        // Halfway in percent is 12 dB below the target
+        assert!((gains[fade_frames / 2] - 10f32.powf(-12.0 / 20.0)).abs() < 0.01);
+        assert_eq!(gains[fade_frames], 1.0);
+
+        // The chime starts on the sample the fade ends, over a silent sound
+        let silence: Box<dyn Source<Item = f32> + Send> = Box::new(Zero::new(1, SAMPLE_RATE));
+        let chime = Some(Cue::strike(BellKind::Chime));
+        let samples: Vec<f32> = WakeUp::new(silence, fade, Volume::from_percent(60), chime)
+            .take(2 * (fade_frames + 1))
+            .collect();
+        assert!(samples[..2 * fade_frames].iter().all(|sample| *sample == 0.0));
+        assert!(samples[2 * fade_frames] != 0.0);
+    }
+}
@@ -0,0 +1,361 @@
+use crate::noise::SAMPLE_RATE;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rodio::Source;
+use serde::Deserialize;
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+// Upper bound on strikes ringing at once in the continuous sound
+const MAX_ACTIVE_STRIKES: usize = 16;
+// Notes the continuous sound picks from: a major pentatonic scale over an
+// octave, as ratios to its pitch
+const SCALE: [f32; 6] = [1.0, 9.0 / 8.0, 5.0 / 4.0, 3.0 / 2.0, 5.0 / 3.0, 2.0];
+// Peak level of a one-shot cue
+const CUE_LEVEL: f32 = 0.3;
+// Time between the notes of a two-note chime
+const CHIME_NOTE_GAP: Duration = Duration::from_millis(350);
+
+#[derive(Clone, Copy, PartialEq, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BellKind {
+    // A church-style bell: the hum an octave down and a minor third above
+    Bell,
+    // A singing bowl: long, pure and slowly beating
+    #[default]
+    Bowl,
+    // A tubular or wind chime: a bright, short-lived struck bar
+    Chime,
+}
+
+impl BellKind {
+    // Frequency ratio, level and time to decay by 60 dB of each partial.
+    // Real bells and bars have partials that aren't whole multiples of the
+    // fundamental, and the higher ones die away first.
+    fn partials(self) -> &'static [(f32, f32, f32)] {
+        match self {
+            BellKind::Bell => &[
+                (0.5, 0.6, 9.0),
+                (1.0, 0.8, 6.0),
+                (1.19, 0.5, 4.5),
+                (1.5, 0.3, 3.5),
+                (2.0, 0.5, 3.0),
+                (2.51, 0.25, 2.0),
+                (2.66, 0.2, 1.8),
+                (3.01, 0.15, 1.5),
+            ],
+            BellKind::Bowl => &[(1.0, 1.0, 20.0), (2.71, 0.5, 12.0), (5.02, 0.25, 7.0), (8.0, 0.1, 4.0)],
+            BellKind::Chime => &[(1.0, 1.0, 3.0), (2.76, 0.4, 1.6), (5.4, 0.15, 0.8), (8.93, 0.06, 0.4)],
+        }
+    }
+
+    // Singing bowls aren't perfectly round, so each partial is split in two
+    // slightly detuned modes that beat against each other
+    fn beat_hz(self) -> f32 {
+        match self {
+            BellKind::Bowl => 0.7,
+            BellKind::Bell | BellKind::Chime => 0.0,
+        }
+    }
+
+    // Where the kind sounds most natural
+    pub fn pitch_hz(self) -> f32 {
+        match self {
+            BellKind::Bell => 330.0,
+            BellKind::Bowl => 220.0,
+            BellKind::Chime => 880.0,
+        }
+    }
+}
+
+// One decaying sine, advanced by rotating a phasor
+struct Partial {
+    re: f32,
+    im: f32,
+    step_re: f32,
+    step_im: f32,
+    amplitude: f32,
+    decay: f32,
+}
+
+impl Partial {
+    fn next(&mut self) -> f32 {
+        let re = self.re * self.step_re - self.im * self.step_im;
+        self.im = self.re * self.step_im + self.im * self.step_re;
+        self.re = re;
+        self.amplitude *= self.decay;
+        self.im * self.amplitude
+    }
+}
+
+// A single strike of a bell, bowl or chime, built from its partials. Peaks
+// at no more than `level` and ends once it has rung out.
+pub struct Strike {
+    partials: Vec<Partial>,
+}
+
+impl Strike {
+    pub fn new(kind: BellKind, pitch_hz: f32, level: f32) -> Self {
+        let table = kind.partials();
+        let total: f32 = table.iter().map(|(_, level, _)| level).sum();
+        let beat = kind.beat_hz();
+        let detunes: &[f32] = if beat > 0.0 { &[-0.5, 0.5] } else { &[0.0] };
+
+        let mut partials = Vec::new();
+        for (ratio, partial_level, ring_seconds) in table {
+            for detune in detunes {
+                let freq = pitch_hz * ratio + detune * beat;
+                // Partials above Nyquist would alias
+                if freq >= SAMPLE_RATE as f32 / 2.0 {
+                    continue;
+                }
+                let w = TAU * freq / SAMPLE_RATE as f32;
+                partials.push(Partial {
+                    re: 1.0,
+                    im: 0.0,
+                    step_re: w.cos(),
+                    step_im: w.sin(),
+                    amplitude: level * partial_level / total / detunes.len() as f32,
+                    // -60 dB over the ring time
+                    decay: (-6.9 / (ring_seconds * SAMPLE_RATE as f32)).exp(),
+                });
+            }
+        }
+        Strike { partials }
+    }
+}
+
+impl Iterator for Strike {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.partials.is_empty() {
+            return None;
+        }
+        let sample = self.partials.iter_mut().map(Partial::next).sum();
+        // Forget partials once they've decayed to inaudible (-80 dB)
+        self.partials.retain(|partial| partial.amplitude > 1e-4);
+        Some(sample)
+    }
+}
+
+// A few strikes at set times, played once over the background as a signal:
+// the end of a timer, a change of step, and so on
+pub struct Cue {
+    // Strikes still to start, with the sample they start on, latest first
+    pending: Vec<(u64, Strike)>,
+    ringing: Vec<Strike>,
+    sample: u64,
+}
+
+impl Cue {
+    pub fn new(mut strikes: Vec<(Duration, Strike)>) -> Self {
+        strikes.sort_by_key(|(start, _)| std::cmp::Reverse(*start));
+        Cue {
+            pending: strikes
+                .into_iter()
+                .map(|(start, strike)| ((start.as_secs_f64() * SAMPLE_RATE as f64).round() as u64, strike))
+                .collect(),
+            ringing: Vec::new(),
+            sample: 0,
+        }
+    }
+
+    // One strike at the kind's natural pitch
+    pub fn strike(kind: BellKind) -> Self {
+        Cue::new(vec![(Duration::ZERO, Strike::new(kind, kind.pitch_hz(), CUE_LEVEL))])
+    }
+
+    // Two chime notes a fourth apart, E5 and A5: rising when something
+    // starts, falling when it ends
+    pub fn chime(rising: bool) -> Self {
+        let notes = if rising { [659.26, 880.0] } else { [880.0, 659.26] };
+        Cue::new(vec![
+            (Duration::ZERO, Strike::new(BellKind::Chime, notes[0], CUE_LEVEL / 2.0)),
+            (CHIME_NOTE_GAP, Strike::new(BellKind::Chime, notes[1], CUE_LEVEL / 2.0)),
+        ])
+    }
+}
+
+impl Iterator for Cue {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.last().is_some_and(|(start, _)| *start <= self.sample) {
+            let (_, strike) = self.pending.pop()?;
+            self.ringing.push(strike);
+        }
+        if self.pending.is_empty() && self.ringing.is_empty() {
+            return None;
+        }
+        self.sample += 1;
+
+        let mut sample = 0.0;
+        self.ringing.retain_mut(|strike| match strike.next() {
+            Some(value) => {
+                sample += value;
+                true
+            }
+            None => false,
+        });
+        Some(sample)
+    }
+}
+
+impl Source for Cue {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct BellsParams {
+    pub kind: BellKind,
+    // Average number of strikes, loosely spaced
+    pub strikes_per_minute: f32,
+    // Lowest note; the kind's natural pitch when unset
+    pub pitch_hz: Option<f32>,
+    // Fixed seed for a reproducible sequence; random when unset
+    pub seed: Option<u64>,
+}
+
+impl Default for BellsParams {
+    fn default() -> Self {
+        BellsParams {
+            kind: BellKind::Bowl,
+            strikes_per_minute: 4.0,
+            pitch_hz: None,
+            seed: None,
+        }
+    }
+}
+
+// A continuous meditation layer: loosely timed strikes on the notes of a
+// pentatonic scale in random order, each left to ring out over the others. The first
+// strike comes straight away so starting playback is never silent.
+pub struct Bells {
+    rng: StdRng,
+    kind: BellKind,
+    pitch_hz: f32,
+    strike_rate: f32,
+    // The scale in a random order, used up one note per strike before it's
+    // shuffled again, so no note is left out for long
+    notes: Vec<f32>,
+    strikes: Vec<Strike>,
+    samples_until_strike: f32,
+}
+
+impl Bells {
+    pub fn new(params: BellsParams) -> Self {
+        let rng = match params.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Bells {
+            rng,
+            kind: params.kind,
+            pitch_hz: params.pitch_hz.unwrap_or(params.kind.pitch_hz()).clamp(40.0, 4000.0),
+            strike_rate: params.strikes_per_minute.clamp(0.5, 60.0) / 60.0,
+            notes: Vec::new(),
+            strikes: Vec::with_capacity(MAX_ACTIVE_STRIKES),
+            samples_until_strike: 0.0,
+        }
+    }
+
+    fn strike(&mut self) {
+        if self.strikes.len() < MAX_ACTIVE_STRIKES {
+            if self.notes.is_empty() {
+                self.notes = SCALE.to_vec();
+                self.notes.shuffle(&mut self.rng);
+            }
+            let note = self.notes.pop().unwrap_or(1.0);
+            let level = self.rng.gen_range(0.6..1.0);
+            self.strikes.push(Strike::new(self.kind, self.pitch_hz * note, level));
+        }
+        // Unhurried but not mechanical: within 40% of the average gap
+        let gap = self.rng.gen_range(0.6..1.4) / self.strike_rate;
+        self.samples_until_strike += gap * SAMPLE_RATE as f32;
+    }
+}
+
+impl Iterator for Bells {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.samples_until_strike -= 1.0;
+        while self.samples_until_strike <= 0.0 {
+            self.strike();
+        }
+
+        let mut sample = 0.0;
+        self.strikes.retain_mut(|strike| match strike.next() {
+            Some(value) => {
+                sample += value;
+                true
+            }
+            None => false,
+        });
+        Some(sample)
+    }
+}
+
+impl Source for Bells {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_strike_rings_out_and_ends() {
+        for kind in [BellKind::Bell, BellKind::Bowl, BellKind::Chime] {
+            let samples: Vec<f32> = Strike::new(kind, kind.pitch_hz(), 0.5).collect();
+            let peak = samples.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            assert!(peak > 0.2 && peak <= 0.5, "{:?} peaks at {}", kind, peak);
+            // Ends within a few ring times of the longest partial
+            let seconds = samples.len() as f32 / SAMPLE_RATE as f32;
+            assert!(seconds < 40.0, "{:?} rings for {} s", kind, seconds);
+            let tail = &samples[samples.len() - 480..];
+            assert!(tail.iter().all(|sample| sample.abs() < 1e-3));
+        }
+    }
+
+    #[test]
+    fn chime_notes_start_on_time() {
+        let samples: Vec<f32> = Cue::chime(true).collect();
+        let gap = (CHIME_NOTE_GAP.as_secs_f32() * SAMPLE_RATE as f32) as usize;
+        // The first note has died down a little by the time the second
+        // strikes, so the level jumps right at the gap
+        let level = |range: std::ops::Range<usize>| samples[range].iter().map(|sample| sample.abs()).fold(0.0, f32::max);
+        assert!(level(gap..gap + 100) > level(gap - 100..gap) * 1.5);
+    }
+}
@@ -0,0 +1,355 @@
+use crate::bell::{BellKind, Strike};
+use crate::eq::Biquad;
+use crate::noise::{SAMPLE_RATE, WhiteNoise};
+use rodio::Source;
+use serde::Deserialize;
+use std::f32::consts::{PI, TAU};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+// Pitch of the tone at the bottom and top of a breath: a fifth apart
+const TONE_LOW_HZ: f32 = 220.0;
+const TONE_HIGH_HZ: f32 = 330.0;
+const TONE_LEVEL: f32 = 0.1;
+// Center of the noise swell at the bottom and top of a breath
+const NOISE_LOW_HZ: f32 = 300.0;
+const NOISE_HIGH_HZ: f32 = 1500.0;
+const NOISE_Q: f32 = 1.5;
+// Band-passed noise keeps only a little of the white noise's power
+const NOISE_LEVEL: f32 = 0.5;
+// A soft chime marks the start of each hold, higher with the lungs full
+const HOLD_LEVEL: f32 = 0.08;
+const HOLD_HIGH_HZ: f32 = 660.0;
+const HOLD_LOW_HZ: f32 = 440.0;
+// The noise filter gets new coefficients this often, in frames
+const RETUNE_FRAMES: u64 = 32;
+// Longest phase allowed in a custom pattern
+const MAX_PHASE_SECONDS: f32 = 60.0;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default, Deserialize)]
+pub enum Pattern {
+    // In, hold, out, hold, 4 seconds each
+    #[default]
+    #[serde(rename = "box")]
+    Box,
+    // In for 4, hold for 7, out for 8
+    #[serde(rename = "4-7-8")]
+    FourSevenEight,
+    // The `*_seconds` values from the config
+    #[serde(rename = "custom")]
+    Custom,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CueSound {
+    // A tone that rises while breathing in and falls while breathing out
+    #[default]
+    Tone,
+    // A swell of filtered noise, like the sound of the breath itself
+    Noise,
+}
+
+// The breathing exercise, from the config's [breathing] table
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct BreathingConfig {
+    pub pattern: Pattern,
+    pub cue: CueSound,
+    // Phase lengths for the custom pattern. Either hold may be 0 to skip it.
+    pub inhale_seconds: f32,
+    pub hold_seconds: f32,
+    pub exhale_seconds: f32,
+    pub rest_seconds: f32,
+}
+
+impl Default for BreathingConfig {
+    fn default() -> Self {
+        // Six slow breaths a minute when the custom pattern is picked
+        BreathingConfig {
+            pattern: Pattern::Box,
+            cue: CueSound::Tone,
+            inhale_seconds: 5.0,
+            hold_seconds: 0.0,
+            exhale_seconds: 5.0,
+            rest_seconds: 0.0,
+        }
+    }
+}
+
+impl BreathingConfig {
+    pub fn breath(&self) -> Result<Breath, String> {
+        let (name, seconds) = match self.pattern {
+            Pattern::Box => ("Box breathing", [4.0, 4.0, 4.0, 4.0]),
+            Pattern::FourSevenEight => ("4-7-8 breathing", [4.0, 7.0, 8.0, 0.0]),
+            Pattern::Custom => (
+                "Breathing",
+                [self.inhale_seconds, self.hold_seconds, self.exhale_seconds, self.rest_seconds],
+            ),
+        };
+        if let Some(bad) = seconds.iter().find(|seconds| !(0.0..=MAX_PHASE_SECONDS).contains(*seconds)) {
+            return Err(format!("breathing phases should be 0 to {} seconds, not {}", MAX_PHASE_SECONDS, bad));
+        }
+        let frames = seconds.map(|seconds| (seconds as f64 * SAMPLE_RATE as f64).round() as u64);
+        // Checked after rounding, so every cycle has a frame to play
+        if frames[0] == 0 || frames[2] == 0 {
+            return Err("breathing in and out need to take some time".to_string());
+        }
+        let phases = [BreathPhase::Inhale, BreathPhase::Hold, BreathPhase::Exhale, BreathPhase::Rest]
+            .into_iter()
+            .zip(frames)
+            .filter(|(_, frames)| *frames > 0)
+            .collect();
+        Ok(Breath { name, phases })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BreathPhase {
+    Inhale,
+    // After breathing in
+    Hold,
+    Exhale,
+    // After breathing out
+    Rest,
+}
+
+impl BreathPhase {
+    fn label(self) -> &'static str {
+        match self {
+            BreathPhase::Inhale => "breathe in",
+            BreathPhase::Hold | BreathPhase::Rest => "hold",
+            BreathPhase::Exhale => "breathe out",
+        }
+    }
+}
+
+// One cycle of a pattern, with every phase a whole number of frames
+#[derive(Clone)]
+pub struct Breath {
+    name: &'static str,
+    phases: Vec<(BreathPhase, u64)>,
+}
+
+impl Breath {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    // The phase `frame` frames in, and how many frames are left of it
+    pub fn at(&self, frame: u64) -> (BreathPhase, u64) {
+        let (index, position) = self.position(frame);
+        let (phase, frames) = self.phases[index];
+        (phase, frames - position)
+    }
+
+    // Index of the phase `frame` frames in, and how far into it
+    fn position(&self, frame: u64) -> (usize, u64) {
+        let cycle: u64 = self.phases.iter().map(|(_, frames)| frames).sum();
+        let mut position = frame % cycle;
+        for (index, (_, frames)) in self.phases.iter().enumerate() {
+            if position < *frames {
+                return (index, position);
+            }
+            position -= frames;
+        }
+        unreachable!("position is within the cycle")
+    }
+
+    // For the tooltip, e.g. "Box breathing: hold, 3"
+    pub fn status(&self, frame: u64) -> String {
+        let (phase, frames_left) = self.at(frame);
+        format!("{}: {}, {}", self.name, phase.label(), frames_left.div_ceil(SAMPLE_RATE as u64))
+    }
+}
+
+// The breathing cues on their own, to be mixed over whatever is playing.
+// Phases change on exact frames, and the number of frames played is published
+// so the tooltip can follow along. Plays until `running` is cleared.
+pub struct Guide {
+    breath: Breath,
+    cue: CueSound,
+    played: Arc<AtomicU64>,
+    running: Arc<AtomicBool>,
+    // Index into the breath's phases, and frames into it
+    phase: usize,
+    frame_in_phase: u64,
+    // Tone oscillator position, in cycles
+    tone_phase: f32,
+    noise: WhiteNoise,
+    filter: Biquad,
+    // Marks the start of a hold
+    strike: Option<Strike>,
+}
+
+impl Guide {
+    // Carries on from the frames already `played`, so a guide can pick up
+    // where the last one left off
+    pub fn new(breath: Breath, cue: CueSound, played: Arc<AtomicU64>, running: Arc<AtomicBool>) -> Self {
+        let (phase, frame_in_phase) = breath.position(played.load(Ordering::Relaxed));
+        Guide {
+            breath,
+            cue,
+            played,
+            running,
+            phase,
+            frame_in_phase,
+            tone_phase: 0.0,
+            noise: WhiteNoise::new(),
+            filter: Biquad::band_pass(NOISE_LOW_HZ, NOISE_Q, SAMPLE_RATE as f32),
+            strike: None,
+        }
+    }
+
+    // A breath in or out: `height` goes from 0 (empty) to 1 (full) and sets
+    // the pitch, `t` is how far through the phase it is
+    fn swell(&mut self, height: f32, t: f32) -> f32 {
+        // Fades in and out with the phase, so the ends don't click
+        let envelope = (PI * t).sin();
+        match self.cue {
+            CueSound::Tone => {
+                let freq = TONE_LOW_HZ * (TONE_HIGH_HZ / TONE_LOW_HZ).powf(height);
+                self.tone_phase = (self.tone_phase + freq / SAMPLE_RATE as f32).fract();
+                (TAU * self.tone_phase).sin() * TONE_LEVEL * envelope
+            }
+            CueSound::Noise => {
+                if self.frame_in_phase.is_multiple_of(RETUNE_FRAMES) {
+                    let center = NOISE_LOW_HZ * (NOISE_HIGH_HZ / NOISE_LOW_HZ).powf(height);
+                    self.filter.retune(&Biquad::band_pass(center, NOISE_Q, SAMPLE_RATE as f32));
+                }
+                let white = self.noise.next().unwrap_or(0.0);
+                self.filter.process(white) * NOISE_LEVEL * envelope
+            }
+        }
+    }
+
+    fn next_frame(&mut self) -> f32 {
+        let (phase, frames) = self.breath.phases[self.phase];
+        let t = self.frame_in_phase as f32 / frames as f32;
+        let swell = match phase {
+            BreathPhase::Inhale => self.swell(t, t),
+            BreathPhase::Exhale => self.swell(1.0 - t, t),
+            BreathPhase::Hold | BreathPhase::Rest => {
+                if self.frame_in_phase == 0 {
+                    let pitch = if phase == BreathPhase::Hold { HOLD_HIGH_HZ } else { HOLD_LOW_HZ };
+                    self.strike = Some(Strike::new(BellKind::Chime, pitch, HOLD_LEVEL));
+                }
+                0.0
+            }
+        };
+        let strike = self.strike.as_mut().and_then(Iterator::next).unwrap_or(0.0);
+
+        self.frame_in_phase += 1;
+        if self.frame_in_phase == frames {
+            self.frame_in_phase = 0;
+            self.phase = (self.phase + 1) % self.breath.phases.len();
+        }
+        self.played.fetch_add(1, Ordering::Relaxed);
+        swell + strike
+    }
+}
+
+impl Iterator for Guide {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.running.load(Ordering::Relaxed) {
+            return None;
+        }
+        Some(self.next_frame())
+    }
+}
+
+impl Source for Guide {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom(seconds: [f32; 4]) -> BreathingConfig {
+        BreathingConfig {
+            pattern: Pattern::Custom,
+            inhale_seconds: seconds[0],
+            hold_seconds: seconds[1],
+            exhale_seconds: seconds[2],
+            rest_seconds: seconds[3],
+            ..BreathingConfig::default()
+        }
+    }
+
+    #[test]
+    fn patterns_change_phase_on_the_exact_frame() {
+        let second = SAMPLE_RATE as u64;
+        let config = BreathingConfig { pattern: Pattern::FourSevenEight, ..BreathingConfig::default() };
+        let breath = config.breath().unwrap();
+        assert_eq!(breath.at(0), (BreathPhase::Inhale, 4 * second));
+        assert_eq!(breath.at(4 * second - 1), (BreathPhase::Inhale, 1));
+        assert_eq!(breath.at(4 * second), (BreathPhase::Hold, 7 * second));
+        // No rest after breathing out: straight back to breathing in
+        assert_eq!(breath.at(19 * second - 1), (BreathPhase::Exhale, 1));
+        assert_eq!(breath.at(19 * second), (BreathPhase::Inhale, 4 * second));
+        assert_eq!(breath.status(4 * second + 1), "4-7-8 breathing: hold, 7");
+
+        assert!(custom([4.0, 0.0, 6.0, 0.0]).breath().is_ok());
+        assert!(custom([0.0, 2.0, 6.0, 0.0]).breath().is_err());
+        assert!(custom([4.0, -1.0, 6.0, 0.0]).breath().is_err());
+        // Too short to last a single frame
+        assert!(custom([4.0, 0.0, 0.00001, 0.0]).breath().is_err());
+        assert!(custom([f32::NAN, 0.0, 6.0, 0.0]).breath().is_err());
+    }
+
+    #[test]
+    fn cues_follow_the_phases() {
+        let breath = custom([0.5, 0.25, 0.5, 0.0]).breath().unwrap();
+        let hold = SAMPLE_RATE as usize / 2;
+        for cue in [CueSound::Tone, CueSound::Noise] {
+            let played = Arc::new(AtomicU64::new(0));
+            let running = Arc::new(AtomicBool::new(true));
+            let samples: Vec<f32> =
+                Guide::new(breath.clone(), cue, played.clone(), running).take(SAMPLE_RATE as usize).collect();
+            assert_eq!(played.load(Ordering::Relaxed), SAMPLE_RATE as u64);
+
+            let level =
+                |range: std::ops::Range<usize>| samples[range].iter().map(|sample| sample.abs()).fold(0.0, f32::max);
+            // The breath in swells up in the middle and fades out at the end
+            assert!(level(0..10) < 0.01 && level(hold / 2 - 500..hold / 2 + 500) > 0.05, "{:?}", cue);
+            // The hold's chime strikes on its first frame
+            assert!(level(hold..hold + 100) > 10.0 * level(hold - 100..hold), "{:?}", cue);
+        }
+    }
+
+    #[test]
+    fn a_new_guide_carries_on_and_stops_when_told() {
+        let breath = custom([0.5, 0.25, 0.5, 0.0]).breath().unwrap();
+        let played = Arc::new(AtomicU64::new(0));
+        let running = Arc::new(AtomicBool::new(true));
+        let hold = SAMPLE_RATE as u64 / 2;
+        Guide::new(breath.clone(), CueSound::Tone, played.clone(), running.clone()).take(hold as usize + 10).for_each(drop);
+
+        // Picks up ten frames into the hold, where the last one stopped
+        let mut guide = Guide::new(breath, CueSound::Tone, played.clone(), running.clone());
+        assert_eq!((guide.phase, guide.frame_in_phase), (1, 10));
+        guide.by_ref().take(10).for_each(drop);
+        assert_eq!(played.load(Ordering::Relaxed), hold + 20);
+
+        running.store(false, Ordering::Relaxed);
+        assert_eq!(guide.next(), None);
+    }
+}
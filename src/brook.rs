@@ -0,0 +1,204 @@
+use crate::eq::Biquad;
+use crate::noise::{PinkNoise, SAMPLE_RATE};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use rodio::Source;
+use serde::Deserialize;
+use std::f32::consts::TAU;
+
+// Bubbles per second at flow rate 0.0 and 1.0
+const MIN_BUBBLE_RATE: f32 = 150.0;
+const MAX_BUBBLE_RATE: f32 = 2500.0;
+// Upper bound on overlapping bubbles, which keeps the CPU cost fixed
+const MAX_ACTIVE_BUBBLES: usize = 96;
+// How far a bubble's pitch rises over one decay time constant
+const CHIRP: f32 = 0.1;
+// The gurgle is retimed this often, in samples
+const GURGLE_INTERVAL: u32 = SAMPLE_RATE / 20;
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct BrookParams {
+    // From a trickle (0.0) to a rushing stream (1.0)
+    pub flow_rate: f32,
+    // Fixed seed for a reproducible brook; random when unset
+    pub seed: Option<u64>,
+}
+
+impl Default for BrookParams {
+    fn default() -> Self {
+        BrookParams {
+            flow_rate: 0.5,
+            seed: None,
+        }
+    }
+}
+
+// A single bubble: a decaying sine whose pitch rises as it reaches the
+// surface. The phasor is advanced by a rotation that is itself rotated a
+// little further every sample, so the chirp needs no trig per sample.
+struct Bubble {
+    re: f32,
+    im: f32,
+    step_re: f32,
+    step_im: f32,
+    chirp_re: f32,
+    chirp_im: f32,
+    amplitude: f32,
+    decay: f32,
+}
+
+impl Bubble {
+    fn next(&mut self) -> f32 {
+        let re = self.re * self.step_re - self.im * self.step_im;
+        self.im = self.re * self.step_im + self.im * self.step_re;
+        self.re = re;
+        let step_re = self.step_re * self.chirp_re - self.step_im * self.chirp_im;
+        self.step_im = self.step_re * self.chirp_im + self.step_im * self.chirp_re;
+        self.step_re = step_re;
+        self.amplitude *= self.decay;
+        self.im * self.amplitude
+    }
+}
+
+// Running water: a band-limited pink noise bed plus hundreds of resonating
+// bubbles a second. The bubble rate wobbles every 50 ms, which gives the
+// irregular gurgle of water over stones.
+pub struct Brook {
+    rng: StdRng,
+    bed: PinkNoise,
+    bed_high_pass: Biquad,
+    bed_low_pass: Biquad,
+    bed_gain: f32,
+    bubbles: Vec<Bubble>,
+    bubble_rate: f32,
+    // Multiplier on the bubble rate, averaging 1.0
+    gurgle: f32,
+    samples_until_gurgle: u32,
+    samples_until_bubble: f32,
+}
+
+impl Brook {
+    pub fn new(params: BrookParams) -> Self {
+        let mut rng = match params.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let flow_rate = params.flow_rate.clamp(0.0, 1.0);
+        let sample_rate = SAMPLE_RATE as f32;
+
+        let mut brook = Brook {
+            bed: PinkNoise::with_seed(rng.next_u64()),
+            bed_high_pass: Biquad::high_pass(200.0, sample_rate),
+            bed_low_pass: Biquad::low_pass(2000.0 + 2000.0 * flow_rate, sample_rate),
+            bed_gain: 0.15 + 0.25 * flow_rate,
+            bubbles: Vec::with_capacity(MAX_ACTIVE_BUBBLES),
+            bubble_rate: MIN_BUBBLE_RATE + (MAX_BUBBLE_RATE - MIN_BUBBLE_RATE) * flow_rate * flow_rate,
+            gurgle: 1.0,
+            samples_until_gurgle: GURGLE_INTERVAL,
+            samples_until_bubble: 0.0,
+            rng,
+        };
+        brook.schedule_next_bubble();
+        brook
+    }
+
+    // Bubbles are a Poisson process at the current rate
+    fn schedule_next_bubble(&mut self) {
+        let u: f32 = self.rng.gen_range(f32::EPSILON..1.0);
+        self.samples_until_bubble += -u.ln() / (self.bubble_rate * self.gurgle) * SAMPLE_RATE as f32;
+    }
+
+    fn spawn_bubble(&mut self) {
+        if self.bubbles.len() >= MAX_ACTIVE_BUBBLES {
+            return;
+        }
+
+        // Most bubbles are small and high; the big low ones are rare but loud.
+        // Damping after van den Doel's bubble model: small bubbles ring shorter.
+        let size: f32 = self.rng.gen_range(0.0f32..1.0).powi(2);
+        let freq = 3500.0 - 3000.0 * size;
+        let damping = 0.043 * freq + 0.0014 * freq.powf(1.5);
+        let w = TAU * freq / SAMPLE_RATE as f32;
+        let dw = w * CHIRP * damping / SAMPLE_RATE as f32;
+
+        self.bubbles.push(Bubble {
+            re: 1.0,
+            im: 0.0,
+            step_re: w.cos(),
+            step_im: w.sin(),
+            chirp_re: dw.cos(),
+            chirp_im: dw.sin(),
+            amplitude: 0.03 + 0.15 * size,
+            decay: (-damping / SAMPLE_RATE as f32).exp(),
+        });
+    }
+}
+
+impl Iterator for Brook {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.samples_until_gurgle -= 1;
+        if self.samples_until_gurgle == 0 {
+            self.gurgle = self.rng.gen_range(0.3..1.7);
+            self.samples_until_gurgle = GURGLE_INTERVAL;
+        }
+
+        let pink = self.bed.next()?;
+        let bed = self.bed_low_pass.process(self.bed_high_pass.process(pink)) * self.bed_gain;
+
+        self.samples_until_bubble -= 1.0;
+        while self.samples_until_bubble <= 0.0 {
+            self.spawn_bubble();
+            self.schedule_next_bubble();
+        }
+
+        let bubbles: f32 = self.bubbles.iter_mut().map(Bubble::next).sum();
+        // Forget bubbles once they've decayed to inaudible (-80 dB)
+        self.bubbles.retain(|bubble| bubble.amplitude > 1e-4);
+
+        Some(bed + bubbles)
+    }
+}
+
+impl Source for Brook {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loudness(flow_rate: f32) -> f32 {
+        let brook = Brook::new(BrookParams {
+            flow_rate,
+            seed: Some(6),
+        });
+        let samples: Vec<f32> = brook.take(48000 * 5).collect();
+        crate::loudness::integrated_lufs(&samples, 1)
+    }
+
+    #[test]
+    fn more_flow_sounds_louder() {
+        let trickle = loudness(0.0);
+        let stream = loudness(0.5);
+        let torrent = loudness(1.0);
+        assert!(stream > trickle + 3.0, "{:.2} vs {:.2} LUFS", stream, trickle);
+        assert!(torrent > stream + 3.0, "{:.2} vs {:.2} LUFS", torrent, stream);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::volume::Volume;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: playsoundrust [OPTIONS]
+
+Options:
+  --volume <LEVEL>     Starting volume, 0-100 in 1% steps (e.g. 35 or 35%)
+                       or a decibel value (e.g. -12dB)
+  --volume-db <DB>     Starting volume in decibels, 0 dB is the maximum
+  --favorite <NAME>    Start playing a saved favorite
+  --list-favorites     Print the names of the saved favorites
+  --rename-favorite <NAME> <NEW_NAME>
+                       Give a saved favorite a name of your own
+  --export-favorites <FILE>
+                       Write the saved favorites to a preset file
+  --import-favorites <FILE>
+                       Add the favorites in a preset file, replacing any
+                       with the same names
+  -h, --help           Print this help";
+
+// Command line options, applied once at startup
+#[derive(Default)]
+pub struct CliArgs {
+    pub volume: Option<Volume>,
+    pub favorite: Option<String>,
+    pub list_favorites: bool,
+    // Current and new name
+    pub rename_favorite: Option<(String, String)>,
+    pub export_favorites: Option<PathBuf>,
+    pub import_favorites: Option<PathBuf>,
+    pub help: bool,
+}
+
+impl CliArgs {
+    // Parses the arguments after the program name
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut cli = CliArgs::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => cli.help = true,
+                "--volume" => {
+                    let value = args.next().ok_or("--volume needs a value")?;
+                    cli.volume = Some(value.parse()?);
+                }
+                "--volume-db" => {
+                    let value = args.next().ok_or("--volume-db needs a value")?;
+                    let value = value.trim().trim_end_matches("dB").trim_end_matches("db");
+                    cli.volume = Some(format!("{}dB", value).parse()?);
+                }
+                "--favorite" => {
+                    cli.favorite = Some(args.next().ok_or("--favorite needs a name")?);
+                }
+                "--list-favorites" => cli.list_favorites = true,
+                "--rename-favorite" => {
+                    let name = args.next().ok_or("--rename-favorite needs a name")?;
+                    let new_name = args.next().ok_or("--rename-favorite needs a new name")?;
+                    cli.rename_favorite = Some((name, new_name));
+                }
+                "--export-favorites" => {
+                    cli.export_favorites = Some(args.next().ok_or("--export-favorites needs a file")?.into());
+                }
+                "--import-favorites" => {
+                    cli.import_favorites = Some(args.next().ok_or("--import-favorites needs a file")?.into());
+                }
+                _ => return Err(format!("unknown argument \"{}\"", arg)),
+            }
+        }
+
+        Ok(cli)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliArgs, String> {
+        CliArgs::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_volume_options() {
+        assert_eq!(parse(&["--volume", "35%"]).unwrap().volume, Some(Volume::from_percent(35)));
+        assert_eq!(parse(&["--volume", "-12dB"]).unwrap().volume, Some(Volume::from_percent(70)));
+        // The suffix is optional with --volume-db
+        assert_eq!(parse(&["--volume-db", "-12"]).unwrap().volume, Some(Volume::from_percent(70)));
+        assert_eq!(parse(&["--volume-db", "-12dB"]).unwrap().volume, Some(Volume::from_percent(70)));
+        assert!(parse(&["--volume-db", "3"]).is_err());
+        assert!(parse(&[]).unwrap().volume.is_none());
+    }
+
+    #[test]
+    fn rejects_missing_values_and_unknown_arguments() {
+        for bad in [
+            &["--volume"][..],
+            &["--volume-db"],
+            &["--favorite"],
+            &["--export-favorites"],
+            &["--rename-favorite", "Deep Focus"],
+            &["--loud"],
+        ] {
+            assert!(parse(bad).is_err(), "accepted {:?}", bad);
+        }
+        let cli = parse(&["--favorite", "Deep Focus", "--list-favorites"]).unwrap();
+        assert_eq!(cli.favorite.as_deref(), Some("Deep Focus"));
+        assert!(cli.list_favorites);
+        let cli = parse(&["--rename-favorite", "Brown noise, Warm, 40%", "Deep Focus"]).unwrap();
+        assert_eq!(cli.rename_favorite, Some(("Brown noise, Warm, 40%".to_string(), "Deep Focus".to_string())));
+    }
+}
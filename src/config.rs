@@ -0,0 +1,177 @@
+use crate::alarm::AlarmConfig;
+use crate::bell::BellsParams;
+use crate::breathing::BreathingConfig;
+use crate::brook::BrookParams;
+use crate::fan::FanParams;
+use crate::fire::FireParams;
+use crate::library::LibraryConfig;
+use crate::modulation::LfoConfig;
+use crate::noise::NoiseParams;
+use crate::ocean::OceanParams;
+use crate::pan::Position;
+use crate::pomodoro::PomodoroConfig;
+use crate::program::ProgramConfig;
+use crate::rain::RainParams;
+use crate::schedule::ScheduleConfig;
+use crate::sound_file::SoundFile;
+use crate::thunderstorm::ThunderstormParams;
+use crate::volume::VolumeScale;
+use crate::wind::WindParams;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+// User-edited configuration, read once at startup from
+// `<config dir>/playsoundrust/config.toml`. Every section is optional, so a
+// missing file or a file with only some keys falls back to the defaults.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    pub hotkeys: HotkeyConfig,
+    pub volume: VolumeConfig,
+    pub sounds: SoundParams,
+    // Slow LFOs per sound, keyed like the tone settings: one
+    // [[modulation.<sound>]] table each, applied in order
+    pub modulation: HashMap<String, Vec<LfoConfig>>,
+    // Where each sound sits in the stereo field, one [position.<sound>]
+    // table each, keyed like the tone settings
+    pub position: HashMap<String, Position>,
+    // Audio files to loop, one [[files]] table each
+    pub files: Vec<SoundFile>,
+    pub library: LibraryConfig,
+    // Timed sequences of sounds, one [[programs]] table each
+    pub programs: Vec<ProgramConfig>,
+    pub pomodoro: PomodoroConfig,
+    pub alarm: AlarmConfig,
+    pub breathing: BreathingConfig,
+    // Times to start and stop playback, one [[schedule]] table each
+    pub schedule: Vec<ScheduleConfig>,
+}
+
+// Global keyboard shortcuts, written like "Ctrl+Alt+P" or "Shift+Super+Up".
+// An empty string disables the shortcut.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct HotkeyConfig {
+    pub toggle_playback: String,
+    pub play: String,
+    pub stop: String,
+    pub volume_up: String,
+    pub volume_down: String,
+}
+
+impl Default for HotkeyConfig {
+    fn default() -> Self {
+        HotkeyConfig {
+            toggle_playback: "Ctrl+Alt+P".to_string(),
+            play: String::new(),
+            stop: String::new(),
+            volume_up: "Ctrl+Alt+Up".to_string(),
+            volume_down: "Ctrl+Alt+Down".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct VolumeConfig {
+    // "percent" or "db"
+    pub scale: VolumeScale,
+    // Percent change for the Volume Up/Down menu items and hotkeys
+    pub step: i32,
+}
+
+impl Default for VolumeConfig {
+    fn default() -> Self {
+        VolumeConfig {
+            scale: VolumeScale::Percent,
+            step: 5,
+        }
+    }
+}
+
+// Parameters for the procedural sounds, one table per sound under [sounds]
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SoundParams {
+    // Shared by white, pink and brown noise
+    pub noise: NoiseParams,
+    pub rain: RainParams,
+    pub ocean: OceanParams,
+    pub wind: WindParams,
+    pub fire: FireParams,
+    // The storm's rain layer uses the [sounds.rain] settings
+    pub thunderstorm: ThunderstormParams,
+    pub fan: FanParams,
+    pub brook: BrookParams,
+    pub bells: BellsParams,
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("playsoundrust"))
+}
+
+// Paths in the config may start with `~/` for the home directory
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+impl Config {
+    // Loads the config file, falling back to defaults if it is missing or invalid
+    pub fn load() -> Self {
+        let Some(path) = config_dir().map(|dir| dir.join(CONFIG_FILE_NAME)) else {
+            return Config::default();
+        };
+
+        if !path.exists() {
+            return Config::default();
+        }
+
+        match Self::load_from(&path) {
+            Ok(config) => {
+                println!("Loaded config from {}", path.display());
+                config
+            }
+            Err(e) => {
+                eprintln!("Error reading config {}: {}", path.display(), e);
+                eprintln!("Using default settings");
+                Config::default()
+            }
+        }
+    }
+
+    pub fn load_from(path: &std::path::Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = std::fs::read_to_string(path)?;
+        let config: Config = toml::from_str(&contents)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        // Zero would make the volume items do nothing, and negative would swap them
+        if !(1..=100).contains(&self.volume.step) {
+            return Err(format!("volume step should be 1 to 100, not {}", self.volume.step));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_a_volume_step_out_of_range() {
+        let parse = |toml: &str| toml::from_str::<Config>(toml).unwrap().validate();
+        assert!(parse("").is_ok());
+        assert!(parse("[volume]\nstep = 100").is_ok());
+        for bad in ["0", "-5", "101"] {
+            assert!(parse(&format!("[volume]\nstep = {}", bad)).is_err(), "accepted {}", bad);
+        }
+    }
+}
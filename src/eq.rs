@@ -0,0 +1,321 @@
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::time::Duration;
+
+// Q for the high-pass and low-pass cutoffs (Butterworth, no resonance bump)
+const CUTOFF_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+// Tone shaping for one sound. Every stage is optional; the default is flat.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EqSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub high_pass_hz: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub low_pass_hz: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub low_shelf: Option<Shelf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub high_shelf: Option<Shelf>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub peaks: Vec<Peak>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Shelf {
+    pub freq_hz: f32,
+    pub gain_db: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Peak {
+    pub freq_hz: f32,
+    pub gain_db: f32,
+    pub q: f32,
+}
+
+// Ready-made settings offered in the tray's "Tone" submenu
+pub const TONE_PRESETS: [(&str, EqSettings); 5] = [
+    ("Flat", EqSettings {
+        high_pass_hz: None,
+        low_pass_hz: None,
+        low_shelf: None,
+        high_shelf: None,
+        peaks: Vec::new(),
+    }),
+    ("Soft Highs", EqSettings {
+        high_pass_hz: None,
+        low_pass_hz: Some(10000.0),
+        low_shelf: None,
+        high_shelf: Some(Shelf { freq_hz: 4000.0, gain_db: -6.0 }),
+        peaks: Vec::new(),
+    }),
+    ("Warm", EqSettings {
+        high_pass_hz: None,
+        low_pass_hz: None,
+        low_shelf: Some(Shelf { freq_hz: 200.0, gain_db: 3.0 }),
+        high_shelf: Some(Shelf { freq_hz: 3000.0, gain_db: -4.0 }),
+        peaks: Vec::new(),
+    }),
+    ("Bright", EqSettings {
+        high_pass_hz: None,
+        low_pass_hz: None,
+        low_shelf: None,
+        high_shelf: Some(Shelf { freq_hz: 4000.0, gain_db: 4.0 }),
+        peaks: Vec::new(),
+    }),
+    ("No Rumble", EqSettings {
+        high_pass_hz: Some(80.0),
+        low_pass_hz: None,
+        low_shelf: None,
+        high_shelf: None,
+        peaks: Vec::new(),
+    }),
+];
+
+impl EqSettings {
+    pub fn is_flat(&self) -> bool {
+        *self == EqSettings::default()
+    }
+
+    // Filter stages in processing order
+    fn stages(&self, sample_rate: f32) -> Vec<Biquad> {
+        // Keep frequencies inside the range the formulas are stable for
+        let clamp = |freq: f32| freq.clamp(10.0, sample_rate * 0.45);
+        let mut stages = Vec::new();
+
+        if let Some(freq) = self.high_pass_hz {
+            stages.push(Biquad::high_pass(clamp(freq), sample_rate));
+        }
+        if let Some(shelf) = self.low_shelf {
+            stages.push(Biquad::low_shelf(clamp(shelf.freq_hz), shelf.gain_db, sample_rate));
+        }
+        for peak in &self.peaks {
+            stages.push(Biquad::peaking(clamp(peak.freq_hz), peak.gain_db, peak.q.max(0.1), sample_rate));
+        }
+        if let Some(shelf) = self.high_shelf {
+            stages.push(Biquad::high_shelf(clamp(shelf.freq_hz), shelf.gain_db, sample_rate));
+        }
+        if let Some(freq) = self.low_pass_hz {
+            stages.push(Biquad::low_pass(clamp(freq), sample_rate));
+        }
+
+        stages
+    }
+}
+
+// Second-order filter section with coefficients from Robert Bristow-Johnson's
+// "Audio EQ Cookbook", run in transposed direct form II
+#[derive(Clone)]
+pub struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    // Takes unnormalized coefficients and divides everything by a0
+    fn new(b: [f32; 3], a: [f32; 3]) -> Self {
+        Biquad {
+            b0: b[0] / a[0],
+            b1: b[1] / a[0],
+            b2: b[2] / a[0],
+            a1: a[1] / a[0],
+            a2: a[2] / a[0],
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    fn angle(freq: f32, sample_rate: f32) -> (f32, f32) {
+        let w0 = 2.0 * PI * freq / sample_rate;
+        (w0.cos(), w0.sin())
+    }
+
+    pub fn low_pass(freq: f32, sample_rate: f32) -> Self {
+        let (cos, sin) = Self::angle(freq, sample_rate);
+        let alpha = sin / (2.0 * CUTOFF_Q);
+        Biquad::new(
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    pub fn high_pass(freq: f32, sample_rate: f32) -> Self {
+        let (cos, sin) = Self::angle(freq, sample_rate);
+        let alpha = sin / (2.0 * CUTOFF_Q);
+        Biquad::new(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    // Band-pass with 0 dB gain at the center frequency
+    pub fn band_pass(freq: f32, q: f32, sample_rate: f32) -> Self {
+        let (cos, sin) = Self::angle(freq, sample_rate);
+        let alpha = sin / (2.0 * q);
+        Biquad::new([alpha, 0.0, -alpha], [1.0 + alpha, -2.0 * cos, 1.0 - alpha])
+    }
+
+    pub fn peaking(freq: f32, gain_db: f32, q: f32, sample_rate: f32) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        let (cos, sin) = Self::angle(freq, sample_rate);
+        let alpha = sin / (2.0 * q);
+        Biquad::new(
+            [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+            [1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+        )
+    }
+
+    // Shelves use a slope of 1, the steepest without overshoot
+    pub fn low_shelf(freq: f32, gain_db: f32, sample_rate: f32) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        let (cos, sin) = Self::angle(freq, sample_rate);
+        let beta = a.sqrt() * sin * std::f32::consts::SQRT_2;
+        Biquad::new(
+            [
+                a * ((a + 1.0) - (a - 1.0) * cos + beta),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - beta),
+            ],
+            [
+                (a + 1.0) + (a - 1.0) * cos + beta,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - beta,
+            ],
+        )
+    }
+
+    pub fn high_shelf(freq: f32, gain_db: f32, sample_rate: f32) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        let (cos, sin) = Self::angle(freq, sample_rate);
+        let beta = a.sqrt() * sin * std::f32::consts::SQRT_2;
+        Biquad::new(
+            [
+                a * ((a + 1.0) + (a - 1.0) * cos + beta),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - beta),
+            ],
+            [
+                (a + 1.0) - (a - 1.0) * cos + beta,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - beta,
+            ],
+        )
+    }
+
+    // Takes over another filter's coefficients but keeps this one's state, so
+    // the response can change while audio is running through it
+    pub fn retune(&mut self, other: &Biquad) {
+        self.b0 = other.b0;
+        self.b1 = other.b1;
+        self.b2 = other.b2;
+        self.a1 = other.a1;
+        self.a2 = other.a2;
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let output = self.b0 * input + self.z1;
+        self.z1 = self.b1 * input - self.a1 * output + self.z2;
+        self.z2 = self.b2 * input - self.a2 * output;
+        output
+    }
+}
+
+// Applies `EqSettings` to a source, with separate filter state per channel
+pub struct Equalizer<S> {
+    source: S,
+    // One filter chain per channel
+    chains: Vec<Vec<Biquad>>,
+    channel: usize,
+}
+
+impl<S> Equalizer<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(source: S, settings: &EqSettings) -> Self {
+        let stages = settings.stages(source.sample_rate() as f32);
+        let chains = vec![stages; source.channels() as usize];
+        Equalizer {
+            source,
+            chains,
+            channel: 0,
+        }
+    }
+}
+
+impl<S> Iterator for Equalizer<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.source.next()?;
+        let channels = self.chains.len();
+        let chain = &mut self.chains[self.channel];
+        self.channel = (self.channel + 1) % channels;
+        Some(chain.iter_mut().fold(sample, |sample, stage| stage.process(sample)))
+    }
+}
+
+impl<S> Source for Equalizer<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_span_len(&self) -> Option<usize> {
+        self.source.current_span_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::source::SineWave;
+
+    // Level of a sine after filtering, in dB relative to the input, skipping the settling time
+    fn response_db(settings: &EqSettings, freq: f32) -> f32 {
+        let source = SineWave::new(freq).take_duration(Duration::from_secs(1));
+        let output: Vec<f32> = Equalizer::new(source, settings).skip(4800).collect();
+        let rms = (output.iter().map(|s| s * s).sum::<f32>() / output.len() as f32).sqrt();
+        20.0 * (rms * std::f32::consts::SQRT_2).log10()
+    }
+
+    #[test]
+    fn flat_settings_leave_the_signal_alone() {
+        assert!(response_db(&EqSettings::default(), 1000.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn filters_shape_the_response() {
+        let settings = EqSettings {
+            high_pass_hz: Some(100.0),
+            low_pass_hz: Some(8000.0),
+            peaks: vec![Peak { freq_hz: 1000.0, gain_db: -6.0, q: 1.0 }],
+            ..EqSettings::default()
+        };
+
+        assert!((response_db(&settings, 1000.0) + 6.0).abs() < 0.2);
+        // Well past the cutoffs, 12 dB/octave
+        assert!(response_db(&settings, 25.0) < -20.0);
+        assert!(response_db(&settings, 20000.0) < -15.0);
+    }
+}
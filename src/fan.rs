@@ -0,0 +1,154 @@
+use crate::eq::Biquad;
+use crate::noise::{PinkNoise, SAMPLE_RATE};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use rodio::Source;
+use serde::Deserialize;
+use std::f32::consts::TAU;
+
+// Rotation rate at speed 0.0 and 1.0, in revolutions per second
+const MIN_ROTATION_HZ: f32 = 8.0;
+const MAX_ROTATION_HZ: f32 = 20.0;
+const BLADES: f32 = 5.0;
+// Levels of the blade-pass hum and its harmonics, fundamental first
+const HUM_HARMONICS: [f32; 4] = [1.0, 0.5, 0.3, 0.15];
+const HUM_GAIN: f32 = 0.08;
+// How much the sound swells once per revolution, from slight blade imbalance
+const ROTATION_DEPTH: f32 = 0.12;
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct FanParams {
+    // 0.0 is the lowest setting, 1.0 the highest: faster blades, more air
+    pub speed: f32,
+    // Fixed seed for a reproducible fan; random when unset
+    pub seed: Option<u64>,
+}
+
+impl Default for FanParams {
+    fn default() -> Self {
+        FanParams {
+            speed: 0.5,
+            seed: None,
+        }
+    }
+}
+
+// A fan or HVAC unit: rushing air from low-passed pink noise, a tonal hum at
+// the blade-pass frequency with a few harmonics, and a gentle swell once per
+// revolution. The motor speed wanders very slightly so the hum isn't static.
+pub struct Fan {
+    rng: StdRng,
+    air: PinkNoise,
+    air_filter: Biquad,
+    air_gain: f32,
+    rotation_hz: f32,
+    // Fraction of the nominal speed, gliding towards `wobble_target`
+    wobble: f32,
+    wobble_target: f32,
+    // Position in the current revolution, 0.0 to 1.0
+    rotation_phase: f32,
+    // Position in the current blade pass, 0.0 to 1.0
+    blade_phase: f32,
+}
+
+impl Fan {
+    pub fn new(params: FanParams) -> Self {
+        let mut rng = match params.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let speed = params.speed.clamp(0.0, 1.0);
+
+        Fan {
+            air: PinkNoise::with_seed(rng.next_u64()),
+            // Faster fans move more air and sound brighter
+            air_filter: Biquad::low_pass(800.0 + 2400.0 * speed, SAMPLE_RATE as f32),
+            air_gain: 0.6 + 0.4 * speed,
+            rotation_hz: MIN_ROTATION_HZ + (MAX_ROTATION_HZ - MIN_ROTATION_HZ) * speed,
+            wobble: 1.0,
+            wobble_target: 1.0,
+            rotation_phase: 0.0,
+            blade_phase: 0.0,
+            rng,
+        }
+    }
+
+    fn blade_pass_hz(&self) -> f32 {
+        self.rotation_hz * BLADES
+    }
+}
+
+impl Iterator for Fan {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Every couple of seconds, let the motor drift by up to half a percent
+        if self.rng.gen_range(0..SAMPLE_RATE * 2) == 0 {
+            self.wobble_target = self.rng.gen_range(0.995..1.005);
+        }
+        self.wobble += (self.wobble_target - self.wobble) * 0.00005;
+
+        let step = self.wobble / SAMPLE_RATE as f32;
+        self.rotation_phase = (self.rotation_phase + self.rotation_hz * step).fract();
+        self.blade_phase = (self.blade_phase + self.blade_pass_hz() * step).fract();
+
+        let hum: f32 = HUM_HARMONICS
+            .iter()
+            .enumerate()
+            .map(|(i, level)| (TAU * (i + 1) as f32 * self.blade_phase).sin() * level)
+            .sum();
+        let air = self.air_filter.process(self.air.next()?) * self.air_gain;
+        let swell = 1.0 + ROTATION_DEPTH * (TAU * self.rotation_phase).sin();
+
+        Some((air + hum * HUM_GAIN) * swell)
+    }
+}
+
+impl Source for Fan {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Power of the signal at one frequency, by the Goertzel algorithm
+    fn power_at(samples: &[f32], freq: f32) -> f32 {
+        let coeff = 2.0 * (TAU * freq / SAMPLE_RATE as f32).cos();
+        let (mut s1, mut s2) = (0.0, 0.0);
+        for sample in samples {
+            let s0 = sample + coeff * s1 - s2;
+            s2 = s1;
+            s1 = s0;
+        }
+        s1 * s1 + s2 * s2 - coeff * s1 * s2
+    }
+
+    #[test]
+    fn speed_sets_the_blade_pass_frequency() {
+        for speed in [0.0, 1.0] {
+            let fan = Fan::new(FanParams { speed, seed: Some(2) });
+            let blade_pass = fan.blade_pass_hz();
+            let samples: Vec<f32> = fan.take(48000 * 2).collect();
+            // The hum stands well clear of the noise between its harmonics
+            let hum = power_at(&samples, blade_pass);
+            let between = power_at(&samples, blade_pass * 1.5);
+            assert!(hum > between * 100.0, "speed {}: no hum at {} Hz", speed, blade_pass);
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use crate::eq::Biquad;
+use crate::noise::{BrownNoise, SAMPLE_RATE};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use rodio::Source;
+use serde::Deserialize;
+
+// Upper bound on overlapping crackles and pops, which keeps the CPU cost fixed
+const MAX_ACTIVE_IMPULSES: usize = 32;
+// One crackle in this many is a bigger, lower pop
+const POP_ODDS: u32 = 8;
+// Brings the filtered brown noise up to sit under the crackles
+const RUMBLE_GAIN: f32 = 2.0;
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct FireParams {
+    // Average crackles per second
+    pub crackle_rate: f32,
+    // 0.0 is dull and muffled, 1.0 is sharp and snappy
+    pub brightness: f32,
+    // Fixed seed for a reproducible fire; random when unset
+    pub seed: Option<u64>,
+}
+
+impl Default for FireParams {
+    fn default() -> Self {
+        FireParams {
+            crackle_rate: 8.0,
+            brightness: 0.5,
+            seed: None,
+        }
+    }
+}
+
+enum ImpulseKind {
+    // A burst of noise: the dry snap of a crackle
+    Crackle,
+    // A decaying tone: the hollow sound of trapped gas popping
+    Pop { re: f32, im: f32, cos: f32, sin: f32 },
+}
+
+struct Impulse {
+    kind: ImpulseKind,
+    amplitude: f32,
+    decay: f32,
+}
+
+// A fireplace: a flickering low rumble from brown noise, plus crackles and
+// pops at random times. Crackles arrive in bursts because their rate follows
+// a slowly changing "activity" level, like a real fire flaring up and settling.
+pub struct Fire {
+    rng: StdRng,
+    rumble: BrownNoise,
+    rumble_filter: Biquad,
+    // Flicker of the rumble level, gliding towards `flicker_target`
+    flicker: f32,
+    flicker_target: f32,
+    // Multiplier on the crackle rate, averaging 1.0
+    activity: f32,
+    crackle_rate: f32,
+    crackle_filter: Biquad,
+    impulses: Vec<Impulse>,
+    samples_until_crackle: f32,
+    crackles_spawned: u64,
+}
+
+impl Fire {
+    pub fn new(params: FireParams) -> Self {
+        let mut rng = match params.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let sample_rate = SAMPLE_RATE as f32;
+        let brightness = params.brightness.clamp(0.0, 1.0);
+
+        let mut fire = Fire {
+            rumble: BrownNoise::with_seed(rng.next_u64()),
+            rumble_filter: Biquad::low_pass(200.0, sample_rate),
+            flicker: 1.0,
+            flicker_target: 1.0,
+            activity: 1.0,
+            crackle_rate: params.crackle_rate.max(0.0),
+            // Brighter fires let more of the crackles' top end through
+            crackle_filter: Biquad::high_pass(600.0 + 4400.0 * brightness, sample_rate),
+            impulses: Vec::with_capacity(MAX_ACTIVE_IMPULSES),
+            samples_until_crackle: 0.0,
+            crackles_spawned: 0,
+            rng,
+        };
+        fire.schedule_next_crackle();
+        fire
+    }
+
+    // Crackles are a Poisson process at the current rate
+    fn schedule_next_crackle(&mut self) {
+        let rate = self.crackle_rate * self.activity;
+        if rate <= 0.0 {
+            self.samples_until_crackle = f32::INFINITY;
+            return;
+        }
+        let u: f32 = self.rng.gen_range(f32::EPSILON..1.0);
+        self.samples_until_crackle += -u.ln() / rate * SAMPLE_RATE as f32;
+    }
+
+    fn spawn_impulse(&mut self) {
+        self.crackles_spawned += 1;
+        if self.impulses.len() >= MAX_ACTIVE_IMPULSES {
+            return;
+        }
+
+        let (kind, amplitude, decay_ms) = if self.rng.gen_range(0..POP_ODDS) == 0 {
+            let freq = self.rng.gen_range(250.0..800.0);
+            let w = 2.0 * std::f32::consts::PI * freq / SAMPLE_RATE as f32;
+            let kind = ImpulseKind::Pop {
+                re: 1.0,
+                im: 0.0,
+                cos: w.cos(),
+                sin: w.sin(),
+            };
+            (kind, self.rng.gen_range(0.15..0.35), self.rng.gen_range(15.0..40.0))
+        } else {
+            // Mostly faint ticks with the occasional loud snap
+            let amplitude = 0.05 + 0.35 * self.rng.gen_range(0.0f32..1.0).powi(3);
+            (ImpulseKind::Crackle, amplitude, self.rng.gen_range(0.5..3.0))
+        };
+
+        self.impulses.push(Impulse {
+            kind,
+            amplitude,
+            decay: (-1000.0 / (decay_ms * SAMPLE_RATE as f32)).exp(),
+        });
+    }
+}
+
+impl Iterator for Fire {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Roughly every half second, pick a new flicker level and activity
+        if self.rng.gen_range(0..SAMPLE_RATE / 2) == 0 {
+            self.flicker_target = self.rng.gen_range(0.6..1.0);
+            // Exponential with mean 1.0: mostly calm, sometimes flaring up
+            self.activity = -self.rng.gen_range(f32::EPSILON..1.0).ln();
+            // Gaps are memoryless, so the pending one can be redrawn at the new rate
+            self.samples_until_crackle = 0.0;
+            self.schedule_next_crackle();
+        }
+        self.flicker += (self.flicker_target - self.flicker) * 0.0001;
+
+        let rumble = self.rumble_filter.process(self.rumble.next()?) * RUMBLE_GAIN * self.flicker;
+
+        self.samples_until_crackle -= 1.0;
+        while self.samples_until_crackle <= 0.0 {
+            self.spawn_impulse();
+            self.schedule_next_crackle();
+        }
+
+        let mut crackles = 0.0;
+        let mut pops = 0.0;
+        for impulse in &mut self.impulses {
+            impulse.amplitude *= impulse.decay;
+            match &mut impulse.kind {
+                ImpulseKind::Crackle => {
+                    crackles += self.rng.gen_range(-1.0..1.0) * impulse.amplitude;
+                }
+                ImpulseKind::Pop { re, im, cos, sin } => {
+                    let next_re = *re * *cos - *im * *sin;
+                    *im = *re * *sin + *im * *cos;
+                    *re = next_re;
+                    pops += *im * impulse.amplitude;
+                }
+            }
+        }
+        // Forget impulses once they've decayed to inaudible (-80 dB)
+        self.impulses.retain(|impulse| impulse.amplitude > 1e-4);
+
+        Some(rumble + self.crackle_filter.process(crackles) + pops)
+    }
+}
+
+impl Source for Fire {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fire(crackle_rate: f32, seed: u64) -> Fire {
+        Fire::new(FireParams {
+            crackle_rate,
+            seed: Some(seed),
+            ..FireParams::default()
+        })
+    }
+
+    #[test]
+    fn brightness_thins_out_the_crackles() {
+        // The crackle filter's cutoff moves up with brightness, so the
+        // crackles keep their top end but lose their middle. Same seed, so
+        // the rumble and pops don't change.
+        let band = |samples: &[f32], freq: f32| {
+            let mut filter = Biquad::band_pass(freq, 4.0, SAMPLE_RATE as f32);
+            samples.iter().map(|s| filter.process(*s).powi(2)).sum::<f32>()
+        };
+        let top_to_middle = |brightness: f32| {
+            let samples: Vec<f32> = Fire::new(FireParams { brightness, seed: Some(3), ..FireParams::default() })
+                .take(10 * SAMPLE_RATE as usize)
+                .collect();
+            band(&samples, 12000.0) / band(&samples, 3000.0)
+        };
+        let (dull, medium, bright) = (top_to_middle(0.0), top_to_middle(0.5), top_to_middle(1.0));
+        assert!(dull < medium && medium < bright && bright > 1.5 * dull, "{} {} {}", dull, medium, bright);
+    }
+
+    #[test]
+    fn crackles_follow_the_configured_rate() {
+        let seconds = 120;
+        for rate in [2.0, 8.0, 20.0] {
+            let mut fire = fire(rate, 11);
+            fire.by_ref().take(48000 * seconds).for_each(drop);
+            let measured = fire.crackles_spawned as f32 / seconds as f32;
+            assert!(
+                (measured - rate).abs() < rate * 0.25,
+                "expected {} crackles/s, got {:.2}",
+                rate,
+                measured
+            );
+        }
+    }
+}
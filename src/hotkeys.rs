@@ -0,0 +1,75 @@
+use crate::Action;
+use crate::config::HotkeyConfig;
+use global_hotkey::{GlobalHotKeyManager, hotkey::HotKey};
+use std::str::FromStr;
+
+// Global keyboard shortcuts registered with the OS. They fire while the tray
+// menu is closed and map onto the same actions as the menu items.
+pub struct Hotkeys {
+    // The manager unregisters every hotkey when dropped, so it must outlive the event loop
+    _manager: GlobalHotKeyManager,
+    bindings: Vec<(u32, Action)>,
+}
+
+impl Hotkeys {
+    // Registers every shortcut from the config. Shortcuts that fail to parse,
+    // are bound twice in the config, or are already taken by another
+    // application are reported and skipped; the rest still work.
+    pub fn register(config: &HotkeyConfig, volume_step: i32) -> Result<Self, global_hotkey::Error> {
+        let manager = GlobalHotKeyManager::new()?;
+        let mut bindings: Vec<(u32, Action)> = Vec::new();
+        let mut names: Vec<(&str, &str)> = Vec::new();
+
+        let wanted = [
+            ("toggle_playback", config.toggle_playback.as_str(), Action::TogglePlayback),
+            ("play", config.play.as_str(), Action::Play),
+            ("stop", config.stop.as_str(), Action::Stop),
+            ("volume_up", config.volume_up.as_str(), Action::StepVolume(volume_step)),
+            ("volume_down", config.volume_down.as_str(), Action::StepVolume(-volume_step)),
+        ];
+
+        for (name, shortcut, action) in wanted {
+            if shortcut.trim().is_empty() {
+                continue;
+            }
+
+            let hotkey = match HotKey::from_str(shortcut) {
+                Ok(hotkey) => hotkey,
+                Err(e) => {
+                    eprintln!("Hotkey conflict: {} = \"{}\" is not a valid shortcut: {}", name, shortcut, e);
+                    continue;
+                }
+            };
+
+            if let Some(index) = bindings.iter().position(|(id, _)| *id == hotkey.id()) {
+                let (other_name, other_shortcut) = names[index];
+                eprintln!(
+                    "Hotkey conflict: {} = \"{}\" is already bound to {} (\"{}\"), ignoring it",
+                    name, shortcut, other_name, other_shortcut
+                );
+                continue;
+            }
+
+            if let Err(e) = manager.register(hotkey) {
+                eprintln!("Hotkey conflict: could not register {} = \"{}\": {}", name, shortcut, e);
+                continue;
+            }
+
+            println!("Registered hotkey {} for {}", shortcut, name);
+            bindings.push((hotkey.id(), action));
+            names.push((name, shortcut));
+        }
+
+        Ok(Hotkeys {
+            _manager: manager,
+            bindings,
+        })
+    }
+
+    pub fn action_for(&self, hotkey_id: u32) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(id, _)| *id == hotkey_id)
+            .map(|(_, action)| action.clone())
+    }
+}
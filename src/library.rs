@@ -0,0 +1,155 @@
+use crate::config::{config_dir, expand_home};
+use crate::sound_file::{FileSettings, SoundFile};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// File types the decoders can play
+const AUDIO_EXTENSIONS: [&str; 4] = ["wav", "flac", "ogg", "mp3"];
+// Settings for `creek.flac` go in `creek.flac.toml` next to it
+const SIDECAR_EXTENSION: &str = "toml";
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct LibraryConfig {
+    // Folder of audio files for the "My Sounds" submenu; `sounds` in the
+    // config directory when unset
+    pub dir: Option<PathBuf>,
+}
+
+impl LibraryConfig {
+    pub fn dir(&self) -> Option<PathBuf> {
+        match &self.dir {
+            Some(dir) => Some(expand_home(dir)),
+            None => config_dir().map(|dir| dir.join("sounds")),
+        }
+    }
+}
+
+// Every entry in the folder with its modification time
+type Listing = Vec<(PathBuf, Option<SystemTime>)>;
+
+// The audio files in the sound library folder. There is no file watching in
+// the standard library, so `poll` compares the folder listing and
+// modification times with the last scan, and rescans when anything changed.
+pub struct Library {
+    dir: PathBuf,
+    listing: Listing,
+    files: Vec<SoundFile>,
+}
+
+impl Library {
+    pub fn new(dir: PathBuf) -> Self {
+        let listing = list(&dir);
+        let files = scan(&listing);
+        println!("Found {} sounds in {}", files.len(), dir.display());
+        Library { dir, listing, files }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn files(&self) -> &[SoundFile] {
+        &self.files
+    }
+
+    // Rescans if the folder changed since the last call. Returns whether it did.
+    pub fn poll(&mut self) -> bool {
+        let listing = list(&self.dir);
+        if listing == self.listing {
+            return false;
+        }
+        self.files = scan(&listing);
+        self.listing = listing;
+        println!("Sound library changed, found {} sounds", self.files.len());
+        true
+    }
+}
+
+// A missing folder is just an empty library
+fn list(dir: &Path) -> Listing {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut listing: Listing = entries
+        .flatten()
+        .map(|entry| {
+            let modified = entry.metadata().and_then(|metadata| metadata.modified()).ok();
+            (entry.path(), modified)
+        })
+        .collect();
+    listing.sort();
+    listing
+}
+
+fn is_audio(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| AUDIO_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(SIDECAR_EXTENSION);
+    PathBuf::from(name)
+}
+
+// A broken sidecar is reported and the file plays with default settings
+fn read_sidecar(path: &Path) -> FileSettings {
+    let sidecar = sidecar_path(path);
+    let Ok(contents) = std::fs::read_to_string(&sidecar) else {
+        return FileSettings::default();
+    };
+    match toml::from_str(&contents) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", sidecar.display(), e);
+            FileSettings::default()
+        }
+    }
+}
+
+// Audio files sorted by menu label
+fn scan(listing: &Listing) -> Vec<SoundFile> {
+    let mut files: Vec<SoundFile> = listing
+        .iter()
+        .filter(|(path, _)| path.is_file() && is_audio(path))
+        .map(|(path, _)| SoundFile {
+            path: path.clone(),
+            settings: read_sidecar(path),
+        })
+        .collect();
+    files.sort_by_key(|file| file.label().to_lowercase());
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_up_audio_files_and_their_sidecars() {
+        let dir = std::env::temp_dir().join(format!("playsoundrust-library-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("creek.flac"), b"").unwrap();
+        std::fs::write(dir.join("creek.flac.toml"), "name = \"A Creek\"\ngain_db = -3.0\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), b"").unwrap();
+
+        let mut library = Library::new(dir.clone());
+        assert_eq!(library.files().len(), 1);
+        assert_eq!(library.files()[0].label(), "A Creek");
+        assert_eq!(library.files()[0].settings.gain_db, -3.0);
+        assert!(!library.poll());
+
+        std::fs::write(dir.join("Birds.MP3"), b"").unwrap();
+        assert!(library.poll());
+        let labels: Vec<String> = library.files().iter().map(SoundFile::label).collect();
+        assert_eq!(labels, ["A Creek", "Birds"]);
+
+        std::fs::remove_dir_all(&dir).ok();
+        assert!(library.poll());
+        assert!(library.files().is_empty());
+    }
+}
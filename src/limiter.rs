@@ -0,0 +1,235 @@
+use rodio::Source;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Level the limiter holds peaks to (-1 dBFS)
+const THRESHOLD: f32 = 0.891;
+// How far ahead the limiter looks for peaks
+const LOOKAHEAD: Duration = Duration::from_millis(5);
+// How quickly gain recovers after a peak has passed
+const RELEASE: Duration = Duration::from_millis(80);
+// Above this level the soft clipper bends the waveform towards 1.0
+const CLIP_KNEE: f32 = 0.95;
+// Gain reduction below this doesn't count as the limiter being engaged (~0.1 dB)
+const ENGAGED_GAIN: f32 = 0.99;
+
+// Counters describing how often the limiter had to act. Shared with the audio
+// thread, so they can be read while the sound is playing.
+#[derive(Default)]
+pub struct LimiterStats {
+    total_samples: AtomicU64,
+    limited_samples: AtomicU64,
+    clipped_samples: AtomicU64,
+    engagements: AtomicU64,
+}
+
+impl LimiterStats {
+    pub fn engagements(&self) -> u64 {
+        self.engagements.load(Ordering::Relaxed)
+    }
+
+    pub fn clipped_samples(&self) -> u64 {
+        self.clipped_samples.load(Ordering::Relaxed)
+    }
+
+    // Share of samples the limiter reduced in level, in percent
+    pub fn limited_percent(&self) -> f64 {
+        let total = self.total_samples.load(Ordering::Relaxed);
+        if total == 0 {
+            return 0.0;
+        }
+        100.0 * self.limited_samples.load(Ordering::Relaxed) as f64 / total as f64
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "limiter engaged {} times ({:.3}% of samples), soft-clipped {} samples",
+            self.engagements(),
+            self.limited_percent(),
+            self.clipped_samples()
+        )
+    }
+}
+
+// Look-ahead peak limiter followed by a soft clipper, meant as the last stage
+// of the signal chain so nothing sent to the output exceeds full scale.
+//
+// The input is delayed by `LOOKAHEAD`, which gives the gain time to come down
+// smoothly before a peak reaches the output. Gain is shared by all channels so
+// limiting doesn't shift the stereo image. Whatever overshoot the smoothing
+// lets through is caught by the soft clipper, which never outputs more than 1.0.
+//
+// The source must keep the same channel count and sample rate throughout.
+pub struct Limiter<S> {
+    source: S,
+    source_done: bool,
+    delay: VecDeque<f32>,
+    lookahead_samples: usize,
+    // Running maximum of the samples in the look-ahead window, as (index, level)
+    peaks: VecDeque<(u64, f32)>,
+    samples_in: u64,
+    samples_out: u64,
+    gain: f32,
+    attack_coeff: f32,
+    release_coeff: f32,
+    was_limiting: bool,
+    stats: Arc<LimiterStats>,
+}
+
+impl<S> Limiter<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(source: S, stats: Arc<LimiterStats>) -> Self {
+        // Interleaved samples per second, so timings hold for any channel count
+        let rate = source.sample_rate() as f32 * source.channels() as f32;
+        let lookahead_samples = (LOOKAHEAD.as_secs_f32() * rate).round() as usize;
+
+        Limiter {
+            source,
+            source_done: false,
+            delay: VecDeque::with_capacity(lookahead_samples + 1),
+            lookahead_samples,
+            peaks: VecDeque::new(),
+            samples_in: 0,
+            samples_out: 0,
+            gain: 1.0,
+            // Settles within the look-ahead window
+            attack_coeff: 1.0 - (-4.0 / lookahead_samples.max(1) as f32).exp(),
+            release_coeff: 1.0 - (-1.0 / (RELEASE.as_secs_f32() * rate)).exp(),
+            was_limiting: false,
+            stats,
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        let level = sample.abs();
+        while self.peaks.back().is_some_and(|(_, peak)| *peak <= level) {
+            self.peaks.pop_back();
+        }
+        self.peaks.push_back((self.samples_in, level));
+        self.delay.push_back(sample);
+        self.samples_in += 1;
+    }
+}
+
+// Smoothly bends everything above the knee so the output stays below 1.0
+fn soft_clip(sample: f32) -> f32 {
+    let level = sample.abs();
+    if level <= CLIP_KNEE {
+        return sample;
+    }
+    let headroom = 1.0 - CLIP_KNEE;
+    let bent = CLIP_KNEE + headroom * ((level - CLIP_KNEE) / headroom).tanh();
+    bent.copysign(sample)
+}
+
+impl<S> Iterator for Limiter<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Keep the look-ahead window full; once the source ends, drain what's left
+        while !self.source_done && self.delay.len() <= self.lookahead_samples {
+            match self.source.next() {
+                Some(sample) => self.push(sample),
+                None => self.source_done = true,
+            }
+        }
+
+        let sample = self.delay.pop_front()?;
+
+        // Drop peaks that have already left the window
+        while self.peaks.front().is_some_and(|(index, _)| *index < self.samples_out) {
+            self.peaks.pop_front();
+        }
+        self.samples_out += 1;
+
+        let peak = self.peaks.front().map_or(0.0, |(_, peak)| *peak);
+        let target = if peak > THRESHOLD { THRESHOLD / peak } else { 1.0 };
+        if self.samples_out == 1 {
+            // Nothing has been output yet, so start at the right gain
+            self.gain = target;
+        } else {
+            let coeff = if target < self.gain {
+                self.attack_coeff
+            } else {
+                self.release_coeff
+            };
+            self.gain += (target - self.gain) * coeff;
+        }
+
+        let limited = sample * self.gain;
+        let output = soft_clip(limited);
+
+        let limiting = self.gain < ENGAGED_GAIN;
+        self.stats.total_samples.fetch_add(1, Ordering::Relaxed);
+        if limiting {
+            self.stats.limited_samples.fetch_add(1, Ordering::Relaxed);
+            if !self.was_limiting {
+                self.stats.engagements.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        if limited.abs() > CLIP_KNEE {
+            self.stats.clipped_samples.fetch_add(1, Ordering::Relaxed);
+        }
+        self.was_limiting = limiting;
+
+        Some(output)
+    }
+}
+
+impl<S> Source for Limiter<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::source::SineWave;
+
+    #[test]
+    fn loud_input_never_exceeds_full_scale() {
+        let stats = Arc::new(LimiterStats::default());
+        // 12 dB over full scale, with a sudden start
+        let source = SineWave::new(220.0).amplify(4.0).take_duration(Duration::from_secs(1));
+        let peak = Limiter::new(source, stats.clone()).fold(0.0f32, |peak, s| peak.max(s.abs()));
+
+        assert!(peak < 1.0, "peak {}", peak);
+        assert_eq!(stats.engagements(), 1);
+        assert!(stats.limited_percent() > 90.0);
+    }
+
+    #[test]
+    fn quiet_input_passes_through_unchanged() {
+        let stats = Arc::new(LimiterStats::default());
+        let source = SineWave::new(220.0).amplify(0.5).take_duration(Duration::from_millis(500));
+        let input: Vec<f32> = source.clone().collect();
+        let output: Vec<f32> = Limiter::new(source, stats.clone()).collect();
+
+        assert_eq!(input, output);
+        assert_eq!(stats.engagements(), 0);
+        assert_eq!(stats.clipped_samples(), 0);
+    }
+}
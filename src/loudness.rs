@@ -0,0 +1,120 @@
+// Loudness every sound is calibrated to at 100% volume. Leaves enough
+// headroom for the peaks of pink and brown noise.
+pub const TARGET_LUFS: f32 = -20.0;
+
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+// Second-order IIR section in direct form I
+#[cfg(test)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+#[cfg(test)]
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Biquad { b, a, x: [0.0; 2], y: [0.0; 2] }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+// Ungated integrated loudness per ITU-R BS.1770 of interleaved 48 kHz
+// samples. Gating only matters for material with pauses, which the
+// generators don't have.
+#[cfg(test)]
+pub fn integrated_lufs(samples: &[f32], channels: usize) -> f32 {
+    let mut sum_of_squares = 0.0;
+
+    for channel in 0..channels {
+        // K-weighting: high-shelf pre-filter followed by the RLB high-pass
+        let mut shelf = Biquad::new(
+            [1.53512485958697, -2.69169618940638, 1.19839281085285],
+            [-1.69065929318241, 0.73248077421585],
+        );
+        let mut high_pass = Biquad::new([1.0, -2.0, 1.0], [-1.99004745483398, 0.99007225036621]);
+
+        for sample in samples.iter().skip(channel).step_by(channels) {
+            let weighted = high_pass.process(shelf.process(*sample as f64));
+            sum_of_squares += weighted * weighted;
+        }
+    }
+
+    let mean_square = sum_of_squares / (samples.len() / channels) as f64;
+    (-0.691 + 10.0 * mean_square.log10()) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SoundType;
+    use crate::config::SoundParams;
+    use rodio::Source;
+
+    // Long enough for the noise generators' loudness to settle within a tenth of a dB
+    const MEASURE_SECONDS: usize = 20;
+    // Sounds that change over tens of seconds need a few minutes to average out
+    const SLOW_MEASURE_SECONDS: usize = 180;
+
+    fn measure(sound_type: SoundType) -> f32 {
+        // Calibrated at the default parameters. Seeded where possible so the
+        // test is repeatable.
+        let mut params = SoundParams::default();
+        params.rain.seed = Some(1);
+        params.ocean.seed = Some(1);
+        params.wind.seed = Some(1);
+        params.fire.seed = Some(1);
+        params.thunderstorm.seed = Some(1);
+        params.fan.seed = Some(1);
+        params.brook.seed = Some(1);
+        params.bells.seed = Some(1);
+        let seconds = match sound_type {
+            SoundType::Ocean | SoundType::Wind | SoundType::Fire | SoundType::Thunderstorm | SoundType::Bells => {
+                SLOW_MEASURE_SECONDS
+            }
+            _ => MEASURE_SECONDS,
+        };
+        let source = sound_type.source(&params);
+        assert_eq!(source.sample_rate(), 48000);
+        let channels = source.channels() as usize;
+        let samples: Vec<f32> = source.take(48000 * channels * seconds).collect();
+        integrated_lufs(&samples, channels)
+    }
+
+    #[test]
+    fn every_sound_is_calibrated_to_the_target_loudness() {
+        for sound_type in SoundType::ALL {
+            let lufs = measure(sound_type);
+            println!("{}: {:.2} LUFS", sound_type.name(), lufs);
+            assert!(
+                (lufs - TARGET_LUFS).abs() < 0.5,
+                "{} measured {:.2} LUFS, expected {:.1}",
+                sound_type.name(),
+                lufs,
+                TARGET_LUFS
+            );
+        }
+    }
+
+    #[test]
+    fn full_scale_1khz_sine_reads_minus_3_lufs() {
+        // Reference point from BS.1770: a 0 dBFS 1 kHz sine is -3.01 LUFS
+        let samples: Vec<f32> = rodio::source::SineWave::new(1000.0)
+            .take(48000 * 5)
+            .collect();
+        let lufs = integrated_lufs(&samples, 1);
+        assert!((lufs + 3.01).abs() < 0.1, "measured {:.2} LUFS", lufs);
+    }
+}
@@ -1,198 +1,372 @@
+mod alarm;
+mod bell;
+mod breathing;
+mod brook;
+mod cli;
+mod config;
+mod eq;
+mod fan;
+mod fire;
+mod hotkeys;
+mod library;
+mod limiter;
+mod loudness;
+mod mixer;
+mod modulation;
+mod noise;
+mod ocean;
+mod pan;
+mod pomodoro;
+mod preset;
+mod program;
+mod rain;
+mod schedule;
+mod settings;
+mod sound_file;
+mod thunderstorm;
+mod volume;
+mod wind;
+
 use rodio::{OutputStream, OutputStreamBuilder, Sink, Source};
 use rodio::source::SineWave;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tray_icon::{
-    TrayIconBuilder,
-    menu::{Menu, MenuItem, CheckMenuItem, Submenu, MenuEvent},
+    TrayIcon, TrayIconBuilder,
+    menu::{Menu, MenuId, MenuItem, CheckMenuItem, PredefinedMenuItem, Submenu, MenuEvent},
 };
+use chrono::{Local, NaiveTime};
+use global_hotkey::{GlobalHotKeyEvent, HotKeyState};
 use image::{Rgba, RgbaImage};
-use rand::{Rng, SeedableRng};
-use rand::rngs::StdRng;
+use alarm::{Alarm, AlarmConfig, WakeUp};
+use bell::{Bells, Cue};
+use breathing::{Breath, BreathingConfig, Guide};
+use brook::Brook;
+use cli::CliArgs;
+use config::{Config, SoundParams};
+use eq::{Equalizer, TONE_PRESETS};
+use fan::Fan;
+use fire::Fire;
+use hotkeys::Hotkeys;
+use library::Library;
+use limiter::{Limiter, LimiterStats};
+use mixer::{Mixer, MixerInputs};
+use modulation::LfoConfig;
+use noise::{BrownNoise, PinkNoise, WhiteNoise};
+use ocean::Ocean;
+use pan::{Panner, Position};
+use pomodoro::{Phase, Pomodoro, PomodoroConfig};
+use program::{ProgramConfig, Sequence};
+use rain::Rain;
+use schedule::{Change, LocalClock, Scheduler, Slot};
+use settings::{Favorite, Settings};
+use sound_file::{FileSettings, LoopedFile, PlaybackError, SoundFile};
+use thunderstorm::Thunderstorm;
+use volume::{Volume, VolumeScale};
+use wind::Wind;
 
 // Constant for the tone frequency in Hz
 const FREQUENCY_HZ: f32 = 40.0;
 
+// How often the event loop checks for menu and hotkey events
+#[cfg(target_os = "macos")]
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+#[cfg(not(target_os = "macos"))]
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// How often the sound library folder is checked for changes
+const LIBRARY_POLL_INTERVAL: Duration = Duration::from_secs(2);
+// How often settings.toml is checked for changes made by another process
+const SETTINGS_POLL_INTERVAL: Duration = Duration::from_secs(2);
+// How often the schedule is checked against the clock
+const SCHEDULE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Clone, Copy, PartialEq)]
 enum SoundType {
     SineWave,
     WhiteNoise,
     PinkNoise,
     BrownNoise,
+    Rain,
+    Ocean,
+    Wind,
+    Fire,
+    Thunderstorm,
+    Fan,
+    Brook,
+    Bells,
 }
 
-struct AudioState {
-    sink: Option<Sink>,
-    _stream: Option<OutputStream>,
-    is_playing: bool,
-    sound_type: SoundType,
-    volume: f32,
-}
-
-// White noise generator
-struct WhiteNoise {
-    rng: StdRng,
-}
-
-impl WhiteNoise {
-    fn new() -> Self {
-        WhiteNoise {
-            rng: StdRng::from_entropy(),
+impl SoundType {
+    const ALL: [SoundType; 12] = [
+        SoundType::SineWave,
+        SoundType::WhiteNoise,
+        SoundType::PinkNoise,
+        SoundType::BrownNoise,
+        SoundType::Rain,
+        SoundType::Ocean,
+        SoundType::Wind,
+        SoundType::Fire,
+        SoundType::Thunderstorm,
+        SoundType::Fan,
+        SoundType::Brook,
+        SoundType::Bells,
+    ];
+
+    // Stable identifier used as a key in the settings file
+    fn id(self) -> &'static str {
+        match self {
+            SoundType::SineWave => "sine_wave",
+            SoundType::WhiteNoise => "white_noise",
+            SoundType::PinkNoise => "pink_noise",
+            SoundType::BrownNoise => "brown_noise",
+            SoundType::Rain => "rain",
+            SoundType::Ocean => "ocean",
+            SoundType::Wind => "wind",
+            SoundType::Fire => "fire",
+            SoundType::Thunderstorm => "thunderstorm",
+            SoundType::Fan => "fan",
+            SoundType::Brook => "brook",
+            SoundType::Bells => "bells",
         }
     }
-}
 
-impl Iterator for WhiteNoise {
-    type Item = f32;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        Some(self.rng.gen_range(-1.0..1.0))
+    fn from_id(id: &str) -> Option<SoundType> {
+        SoundType::ALL.into_iter().find(|sound_type| sound_type.id() == id)
     }
-}
 
-impl Source for WhiteNoise {
-    fn current_span_len(&self) -> Option<usize> {
-        None
+    // Menu label
+    fn label(self) -> String {
+        match self {
+            SoundType::SineWave => format!("{}Hz Tone", FREQUENCY_HZ as i32),
+            SoundType::WhiteNoise => "White Noise".to_string(),
+            SoundType::PinkNoise => "Pink Noise".to_string(),
+            SoundType::BrownNoise => "Brown Noise".to_string(),
+            SoundType::Rain => "Rain".to_string(),
+            SoundType::Ocean => "Ocean Waves".to_string(),
+            SoundType::Wind => "Wind".to_string(),
+            SoundType::Fire => "Fireplace".to_string(),
+            SoundType::Thunderstorm => "Thunderstorm".to_string(),
+            SoundType::Fan => "Fan".to_string(),
+            SoundType::Brook => "Babbling Brook".to_string(),
+            SoundType::Bells => "Bells".to_string(),
+        }
     }
 
-    fn channels(&self) -> u16 {
-        1
+    // Name used in log messages
+    fn name(self) -> String {
+        match self {
+            SoundType::SineWave => format!("{}Hz tone", FREQUENCY_HZ as i32),
+            _ => self.label().to_lowercase(),
+        }
     }
 
-    fn sample_rate(&self) -> u32 {
-        48000
+    // Loudness of the generator's raw output, measured offline with
+    // `loudness::integrated_lufs`. Re-measure after changing a generator.
+    fn raw_loudness(self) -> f32 {
+        match self {
+            SoundType::SineWave => -9.27,
+            SoundType::WhiteNoise => -1.64,
+            SoundType::PinkNoise => -14.60,
+            SoundType::BrownNoise => -21.74,
+            SoundType::Rain => -14.01,
+            SoundType::Ocean => -25.73,
+            SoundType::Wind => -26.83,
+            SoundType::Fire => -23.91,
+            SoundType::Thunderstorm => -14.03,
+            SoundType::Fan => -19.42,
+            SoundType::Brook => -17.55,
+            SoundType::Bells => -23.26,
+        }
     }
 
-    fn total_duration(&self) -> Option<std::time::Duration> {
-        None
+    // Gain that brings the generator to the common target loudness, so every
+    // sound is equally loud at the same volume setting
+    fn loudness_gain(self) -> f32 {
+        loudness::db_to_gain(loudness::TARGET_LUFS - self.raw_loudness())
     }
-}
 
-// Pink noise generator using Paul Kellett's algorithm
-struct PinkNoise {
-    white_noise: WhiteNoise,
-    b0: f32,
-    b1: f32,
-    b2: f32,
-    b3: f32,
-    b4: f32,
-    b5: f32,
-    b6: f32,
-}
-
-impl PinkNoise {
-    fn new() -> Self {
-        PinkNoise {
-            white_noise: WhiteNoise::new(),
-            b0: 0.0,
-            b1: 0.0,
-            b2: 0.0,
-            b3: 0.0,
-            b4: 0.0,
-            b5: 0.0,
-            b6: 0.0,
+    // The generators are endless, so they are appended to the sink as-is
+    fn source(self, params: &SoundParams) -> Box<dyn Source<Item = f32> + Send> {
+        let gain = self.loudness_gain();
+        match self {
+            SoundType::SineWave => Box::new(SineWave::new(FREQUENCY_HZ).amplify(gain)),
+            SoundType::WhiteNoise => noise::source(WhiteNoise::new, params.noise, gain),
+            SoundType::PinkNoise => noise::source(PinkNoise::new, params.noise, gain),
+            SoundType::BrownNoise => noise::source(BrownNoise::new, params.noise, gain),
+            SoundType::Rain => Box::new(Rain::new(params.rain).amplify(gain)),
+            SoundType::Ocean => Box::new(Ocean::new(params.ocean).amplify(gain)),
+            SoundType::Wind => Box::new(Wind::new(params.wind).amplify(gain)),
+            SoundType::Fire => Box::new(Fire::new(params.fire).amplify(gain)),
+            SoundType::Thunderstorm => {
+                Box::new(Thunderstorm::new(params.rain, params.thunderstorm).amplify(gain))
+            }
+            SoundType::Fan => Box::new(Fan::new(params.fan).amplify(gain)),
+            SoundType::Brook => Box::new(Brook::new(params.brook).amplify(gain)),
+            SoundType::Bells => Box::new(Bells::new(params.bells).amplify(gain)),
         }
     }
 }
 
-impl Iterator for PinkNoise {
-    type Item = f32;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let white = self.white_noise.next()?;
-
-        self.b0 = 0.99886 * self.b0 + white * 0.0555179;
-        self.b1 = 0.99332 * self.b1 + white * 0.0750759;
-        self.b2 = 0.96900 * self.b2 + white * 0.1538520;
-        self.b3 = 0.86650 * self.b3 + white * 0.3104856;
-        self.b4 = 0.55000 * self.b4 + white * 0.5329522;
-        self.b5 = -0.7616 * self.b5 - white * 0.0168980;
-
-        let pink = self.b0 + self.b1 + self.b2 + self.b3 + self.b4 + self.b5 + self.b6 + white * 0.5362;
-        self.b6 = white * 0.115926;
-
-        Some(pink * 0.11) // Scale down to reasonable volume
-    }
+// What to play: a built-in generator or an audio file
+#[derive(Clone, PartialEq)]
+enum Sound {
+    Builtin(SoundType),
+    File(SoundFile),
 }
 
-impl Source for PinkNoise {
-    fn current_span_len(&self) -> Option<usize> {
-        None
+impl Sound {
+    // Key for the sound in the settings file
+    fn id(&self) -> String {
+        match self {
+            Sound::Builtin(sound_type) => sound_type.id().to_string(),
+            Sound::File(file) => file.id(),
+        }
     }
 
-    fn channels(&self) -> u16 {
-        1
+    fn name(&self) -> String {
+        match self {
+            Sound::Builtin(sound_type) => sound_type.name(),
+            Sound::File(file) => file.label(),
+        }
     }
 
-    fn sample_rate(&self) -> u32 {
-        48000
+    fn label(&self) -> String {
+        match self {
+            Sound::Builtin(sound_type) => sound_type.label(),
+            Sound::File(file) => file.label(),
+        }
     }
 
-    fn total_duration(&self) -> Option<std::time::Duration> {
-        None
+    fn source(
+        &self,
+        params: &SoundParams,
+        playback_error: &PlaybackError,
+    ) -> Result<Box<dyn Source<Item = f32> + Send>, Box<dyn std::error::Error>> {
+        match self {
+            Sound::Builtin(sound_type) => Ok(sound_type.source(params)),
+            Sound::File(file) => {
+                let path = file.resolved_path();
+                let points = file.settings.loop_points().map_err(|e| format!("Couldn't play {}: {}", path.display(), e))?;
+                let source = LoopedFile::open(&path, points, playback_error.clone())
+                    .map_err(|e| format!("Couldn't play {}: {}", path.display(), e))?;
+                Ok(Box::new(source.amplify(loudness::db_to_gain(file.settings.gain_db))))
+            }
+        }
     }
 }
 
-// Brown noise generator using 1/f² spectrum (leaky integrator)
-// This produces a smoother, more natural brown noise than simple random walk
-struct BrownNoise {
-    white_noise: WhiteNoise,
-    integrator1: f32,
-    integrator2: f32,
+// Something the user asked for, either from the tray menu or a global hotkey
+#[derive(Clone, PartialEq)]
+enum Action {
+    SelectSound(Sound),
+    // Index into `eq::TONE_PRESETS`, applied to the selected sound
+    SelectTone(usize),
+    SetVolume(Volume),
+    // Relative change in percent
+    StepVolume(i32),
+    Play,
+    // Index into the configured programs
+    RunProgram(usize),
+    TogglePomodoro,
+    ToggleBreathing,
+    ToggleAlarm,
+    SaveFavorite,
+    // Index into the saved favorites
+    RecallFavorite(usize),
+    Stop,
+    TogglePlayback,
+    Quit,
 }
 
-impl BrownNoise {
-    fn new() -> Self {
-        BrownNoise {
-            white_noise: WhiteNoise::new(),
-            integrator1: 0.0,
-            integrator2: 0.0,
-        }
-    }
+// A program that is playing, and how far it has got
+struct RunningProgram {
+    name: String,
+    step_names: Vec<String>,
+    // Updated by the `Sequence` as it moves through the steps
+    progress: Arc<AtomicUsize>,
+    step: usize,
 }
 
-impl Iterator for BrownNoise {
-    type Item = f32;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let white = self.white_noise.next()?;
-        
-        // Two-stage leaky integrator to create 1/f² spectrum
-        // First integrator with time constant ~0.97
-        self.integrator1 = 0.97 * self.integrator1 + 0.03 * white;
-        // Second integrator with time constant ~0.94
-        self.integrator2 = 0.94 * self.integrator2 + 0.06 * white;
-        
-        // Combine the two integrators and scale to reasonable volume
-        Some((self.integrator1 + self.integrator2) * 0.5)
-    }
+// Pomodoro mode: the selected sound during work, the break sound or silence
+// during breaks
+struct RunningPomodoro {
+    cycle: Pomodoro,
+    break_sound: Option<Sound>,
+    // As last shown in the tooltip
+    minutes_left: u64,
 }
 
-impl Source for BrownNoise {
-    fn current_span_len(&self) -> Option<usize> {
-        None
-    }
-
-    fn channels(&self) -> u16 {
-        1
-    }
-
-    fn sample_rate(&self) -> u32 {
-        48000
-    }
+// A breathing exercise mixed over whatever is playing
+struct RunningBreathing {
+    breath: Breath,
+    // Frames the `Guide` has played, which gives the phase
+    played: Arc<AtomicU64>,
+    // Cleared to end the `Guide`
+    running: Arc<AtomicBool>,
+    // As last shown in the tooltip
+    status: String,
+}
 
-    fn total_duration(&self) -> Option<std::time::Duration> {
-        None
-    }
+struct AudioState {
+    sink: Option<Sink>,
+    // Feeds the sink's mixer, for cues over what's playing
+    mixer: MixerInputs,
+    _stream: Option<OutputStream>,
+    is_playing: bool,
+    sound: Sound,
+    volume: Volume,
+    volume_scale: VolumeScale,
+    limiter_stats: Arc<LimiterStats>,
+    settings: Settings,
+    sound_params: SoundParams,
+    modulation: HashMap<String, Vec<LfoConfig>>,
+    positions: HashMap<String, Position>,
+    // Filled in by a file source that had to stop, checked by `poll`
+    playback_error: PlaybackError,
+    // Why the last attempt to play failed, shown in the tray tooltip
+    error: Option<String>,
+    programs: Vec<ProgramConfig>,
+    program: Option<RunningProgram>,
+    pomodoro_config: PomodoroConfig,
+    pomodoro: Option<RunningPomodoro>,
+    breathing_config: BreathingConfig,
+    breathing: Option<RunningBreathing>,
+    alarm_config: AlarmConfig,
+    // Set while the alarm is switched on
+    alarm: Option<Alarm>,
+    // Whether what's playing was started by the schedule, which only stops
+    // its own playback
+    scheduled: bool,
 }
 
 impl AudioState {
     fn new() -> Self {
         AudioState {
             sink: None,
+            mixer: MixerInputs::default(),
             _stream: None,
             is_playing: false,
-            sound_type: SoundType::SineWave,
-            volume: 0.5, // Default to 50% volume
+            sound: Sound::Builtin(SoundType::SineWave),
+            volume: Volume::from_percent(50), // Default to 50% volume
+            volume_scale: VolumeScale::Percent,
+            limiter_stats: Arc::new(LimiterStats::default()),
+            settings: Settings::load_or_default(),
+            sound_params: SoundParams::default(),
+            modulation: HashMap::new(),
+            positions: HashMap::new(),
+            playback_error: Arc::new(Mutex::new(None)),
+            error: None,
+            programs: Vec::new(),
+            program: None,
+            pomodoro_config: PomodoroConfig::default(),
+            pomodoro: None,
+            breathing_config: BreathingConfig::default(),
+            breathing: None,
+            alarm_config: AlarmConfig::default(),
+            alarm: None,
+            scheduled: false,
         }
     }
 
@@ -204,15 +378,74 @@ impl AudioState {
         Ok(())
     }
 
-    fn set_sound_type(&mut self, sound_type: SoundType) {
-        self.sound_type = sound_type;
+    fn set_sound(&mut self, sound: Sound) {
+        self.sound = sound;
+        self.error = None;
+    }
+
+    // Picks up new sidecar settings for a selected library file, or goes back
+    // to the default sound if the file is gone. Playback isn't interrupted.
+    fn library_changed(&mut self, library: &Library) {
+        let Sound::File(selected) = &self.sound else {
+            return;
+        };
+        if !selected.path.starts_with(library.dir()) {
+            return;
+        }
+        match library.files().iter().find(|file| file.path == selected.path) {
+            Some(file) => self.sound = Sound::File(file.clone()),
+            None if !self.is_playing => self.sound = Sound::Builtin(SoundType::SineWave),
+            None => {}
+        }
+    }
+
+    fn set_tone(&mut self, preset: usize) {
+        let (name, eq) = &TONE_PRESETS[preset];
+        self.settings.set_eq(&self.sound.id(), eq.clone());
+        println!("Tone for {} set to {}", self.sound.name(), name);
+        if let Err(e) = self.settings.save() {
+            eprintln!("Error saving settings: {}", e);
+        }
     }
 
-    fn set_volume(&mut self, volume: f32) {
-        self.volume = volume.clamp(0.0, 1.0);
+    // Saves the sound, volume and tone under a name made from them. Saving
+    // the same combination again just replaces it; --rename-favorite gives it
+    // a name of your own.
+    fn save_favorite(&mut self) {
+        let eq = self.settings.eq_for(&self.sound.id());
+        let tone = TONE_PRESETS.iter().find(|(_, preset)| *preset == eq).map_or("Custom", |(name, _)| name);
+        let name = format!("{}, {}, {}", self.sound.label(), tone, self.volume.label(self.volume_scale));
+        self.settings.save_favorite(Favorite {
+            name: name.clone(),
+            sound: self.sound.id(),
+            volume: self.volume.percent(),
+            eq,
+        });
+        println!("Saved favorite {}", name);
+        if let Err(e) = self.settings.save() {
+            eprintln!("Error saving settings: {}", e);
+        }
+    }
+
+    // Switches to a favorite's sound, tone and volume and plays it
+    fn recall_favorite(&mut self, index: usize, sound: Sound) -> Result<(), Box<dyn std::error::Error>> {
+        let favorite = self.settings.favorites[index].clone();
+        self.stop();
+        self.set_sound(sound);
+        self.settings.set_eq(&self.sound.id(), favorite.eq);
+        if let Err(e) = self.settings.save() {
+            eprintln!("Error saving settings: {}", e);
+        }
+        self.set_volume(Volume::from_percent(favorite.volume.into()));
+        println!("Recalled favorite {}", favorite.name);
+        self.play()
+    }
+
+    fn set_volume(&mut self, volume: Volume) {
+        self.volume = volume;
         if let Some(sink) = &self.sink {
-            sink.set_volume(self.volume);
-            println!("Volume set to {}%", (self.volume * 100.0) as i32);
+            sink.set_volume(self.volume.amplitude());
+            println!("Volume set to {}", self.volume.label(self.volume_scale));
         }
     }
 
@@ -224,57 +457,383 @@ impl AudioState {
             return Ok(());
         }
 
-        if let Some(stream) = &self._stream {
-            let sink = Sink::connect_new(stream.mixer());
-            sink.set_volume(self.volume);
+        let source = self.sound_source(&self.sound)?;
+        self.start(source);
+        println!("Started playing {} at {} volume", self.sound.name(), self.volume.label(self.volume_scale));
+        Ok(())
+    }
 
-            match self.sound_type {
-                SoundType::SineWave => {
-                    let source = SineWave::new(FREQUENCY_HZ)
-                        .repeat_infinite();
-                    sink.append(source);
-                    println!("Started playing {}Hz tone at {}% volume", FREQUENCY_HZ as i32, (self.volume * 100.0) as i32);
-                }
-                SoundType::WhiteNoise => {
-                    let source = WhiteNoise::new()
-                        .amplify(0.3) // Base amplify for white noise to prevent it being too loud
-                        .repeat_infinite();
-                    sink.append(source);
-                    println!("Started playing white noise at {}% volume", (self.volume * 100.0) as i32);
-                }
-                SoundType::PinkNoise => {
-                    let source = PinkNoise::new()
-                        .repeat_infinite();
-                    sink.append(source);
-                    println!("Started playing pink noise at {}% volume", (self.volume * 100.0) as i32);
-                }
-                SoundType::BrownNoise => {
-                    let source = BrownNoise::new()
-                        .repeat_infinite();
-                    sink.append(source);
-                    println!("Started playing brown noise at {}% volume", (self.volume * 100.0) as i32);
-                }
+    // A sound with its tone settings, modulation and stereo position applied
+    fn sound_source(&self, sound: &Sound) -> Result<Box<dyn Source<Item = f32> + Send>, Box<dyn std::error::Error>> {
+        let source = sound.source(&self.sound_params, &self.playback_error)?;
+        let eq = self.settings.eq_for(&sound.id());
+        let mut source: Box<dyn Source<Item = f32> + Send> = Box::new(Equalizer::new(source, &eq));
+        if let Some(lfos) = self.modulation.get(&sound.id()) {
+            source = modulation::modulate(source, lfos);
+        }
+        if let Some(position) = self.positions.get(&sound.id()) {
+            source = Box::new(Panner::new(source, *position));
+        }
+        Ok(source)
+    }
+
+    // Plays a source on a new sink, through a mixer so more can join it.
+    // Needs `initialize_audio` first.
+    fn start(&mut self, source: impl Source<Item = f32> + Send + 'static) {
+        let Some(stream) = &self._stream else {
+            return;
+        };
+        let sink = Sink::connect_new(stream.mixer());
+        sink.set_volume(self.volume.amplitude());
+
+        // The limiter is the last stage, so even at 100% nothing clips
+        self.limiter_stats = Arc::new(LimiterStats::default());
+        self.mixer = MixerInputs::default();
+        self.mixer.add(source);
+        sink.append(Limiter::new(Mixer::new(self.mixer.clone()), self.limiter_stats.clone()));
+
+        sink.play();
+        self.sink = Some(sink);
+        self.is_playing = true;
+        self.scheduled = false;
+        self.error = None;
+    }
+
+    // Replaces whatever is playing with a program from the config
+    fn run_program(&mut self, index: usize) -> Result<(), Box<dyn std::error::Error>> {
+        self.initialize_audio()?;
+
+        // Everything that can fail comes first, so a broken program leaves
+        // what's playing alone
+        let program = &self.programs[index];
+        program.validate()?;
+        let mut steps = Vec::new();
+        let mut step_names = Vec::new();
+        for step in &program.steps {
+            let sound = step.sound().map_err(|e| format!("In program \"{}\": {}", program.name, e))?;
+            steps.push(step.step(self.sound_source(&sound)?));
+            step_names.push(sound.name());
+        }
+
+        let progress = Arc::new(AtomicUsize::new(0));
+        let fade_out = Duration::try_from_secs_f32(program.fade_out_seconds.max(0.0))
+            .map_err(|_| format!("In program \"{}\": fade_out_seconds is out of range", program.name))?;
+        let name = program.name.clone();
+        self.stop();
+        self.start(Sequence::new(steps, fade_out, progress.clone()));
+        println!("Started program {} at {} volume", name, self.volume.label(self.volume_scale));
+        self.program = Some(RunningProgram {
+            name,
+            step_names,
+            progress,
+            step: 0,
+        });
+        Ok(())
+    }
+
+    fn start_pomodoro(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.pomodoro_config.validate()?;
+        let break_sound = match &self.pomodoro_config.break_sound {
+            Some(id) => Some(Sound::Builtin(
+                SoundType::from_id(id).ok_or_else(|| format!("Unknown Pomodoro break sound \"{}\"", id))?,
+            )),
+            None => None,
+        };
+        self.stop();
+        self.play()?;
+        if self.pomodoro_config.chime {
+            self.cue(Cue::chime(true));
+        }
+        self.pomodoro = Some(RunningPomodoro {
+            cycle: Pomodoro::new(self.pomodoro_config.clone(), Instant::now()),
+            break_sound,
+            minutes_left: 0,
+        });
+        println!("Started Pomodoro");
+        Ok(())
+    }
+
+    // Switches between the work and break sounds
+    fn pomodoro_phase_changed(&mut self, phase: Phase) -> Result<(), Box<dyn std::error::Error>> {
+        // Keep the cycle and any breathing exercise going through the stop
+        let pomodoro = self.pomodoro.take();
+        let breathing = self.breathing.take();
+        self.stop();
+        self.pomodoro = pomodoro;
+        self.breathing = breathing;
+        let Some(pomodoro) = &self.pomodoro else {
+            return Ok(());
+        };
+        let chime = pomodoro.cycle.config().chime;
+        let break_sound = pomodoro.break_sound.clone();
+
+        if phase == Phase::Work {
+            println!("Pomodoro: back to work");
+            self.play()?;
+        } else {
+            println!("Pomodoro: time for a break");
+            if let Some(sound) = break_sound {
+                let source = self.sound_source(&sound)?;
+                self.start(source);
             }
+        }
+        // Breathing pauses through a silent break and resumes after it
+        self.add_guide();
+        if chime {
+            self.cue(Cue::chime(phase == Phase::Work));
+        }
+        Ok(())
+    }
+
+    // Mixes breathing cues over whatever is playing, starting the selected
+    // sound if nothing is
+    fn start_breathing(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let breath = self.breathing_config.breath()?;
+        if !self.is_playing {
+            self.play()?;
+        }
+        println!("Started {} over {}", breath.name().to_lowercase(), self.playing_name());
+        self.breathing = Some(RunningBreathing {
+            status: breath.status(0),
+            breath,
+            played: Arc::new(AtomicU64::new(0)),
+            running: Arc::new(AtomicBool::new(true)),
+        });
+        self.add_guide();
+        Ok(())
+    }
+
+    // Mixes the breathing cues into the current sink, carrying on from where
+    // they were
+    fn add_guide(&self) {
+        if let Some(breathing) = &self.breathing
+            && self.sink.is_some()
+        {
+            let cue = self.breathing_config.cue;
+            self.mixer.add(Guide::new(breathing.breath.clone(), cue, breathing.played.clone(), breathing.running.clone()));
+        }
+    }
+
+    // Ends the cues and leaves the sound playing
+    fn stop_breathing(&mut self) {
+        if let Some(breathing) = self.breathing.take() {
+            breathing.running.store(false, Ordering::Relaxed);
+            println!("Stopped {}", breathing.breath.name().to_lowercase());
+        }
+    }
+
+    // Plays a cue once over whatever is playing, mixed in before the limiter
+    // so the two together can't clip
+    fn cue(&self, cue: Cue) {
+        if self.sink.as_ref().is_some_and(|sink| !sink.empty()) {
+            self.mixer.add(cue);
+            return;
+        }
+        // Nothing playing, e.g. a break without a sound: the cue plays alone
+        let Some(stream) = &self._stream else {
+            return;
+        };
+        let sink = Sink::connect_new(stream.mixer());
+        sink.set_volume(self.volume.amplitude());
+        sink.append(Limiter::new(cue, Arc::new(LimiterStats::default())));
+        // Plays out on its own
+        sink.detach();
+    }
 
-            sink.play();
-            self.sink = Some(sink);
-            self.is_playing = true;
+    // Switches the alarm on or off. Off until the config gives it a time.
+    fn toggle_alarm(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.alarm.take().is_some() {
+            println!("Wake-up alarm off");
+            return Ok(());
         }
+        let Some(time) = self.alarm_config.time()? else {
+            return Ok(());
+        };
+        // Caught now rather than when the alarm is due
+        self.alarm_config.fade()?;
+        self.alarm_sound()?;
+        self.alarm = Some(Alarm::new(time, Local::now().naive_local()));
+        println!("Wake-up alarm set for {}", time.format("%H:%M"));
+        Ok(())
+    }
 
+    // The configured alarm sound, or the selected one
+    fn alarm_sound(&self) -> Result<Sound, String> {
+        match &self.alarm_config.sound {
+            Some(id) => Ok(Sound::Builtin(
+                SoundType::from_id(id).ok_or_else(|| format!("Unknown alarm sound \"{}\"", id))?,
+            )),
+            None => Ok(self.sound.clone()),
+        }
+    }
+
+    // Replaces whatever is playing with the alarm sound, fading in from silence
+    fn wake_up(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.initialize_audio()?;
+        let sound = self.alarm_sound()?;
+        let fade = self.alarm_config.fade()?;
+        let source = self.sound_source(&sound)?;
+        self.stop();
+
+        let chime = self.alarm_config.chime.map(Cue::strike);
+        self.set_volume(Volume::from_percent(self.alarm_config.volume.into()));
+        self.set_sound(sound);
+        self.start(WakeUp::new(source, fade, self.volume, chime));
+        println!(
+            "Wake-up alarm: fading in {} to {} over {} minutes",
+            self.sound.name(),
+            self.volume.label(self.volume_scale),
+            fade.as_secs() / 60
+        );
+        Ok(())
+    }
+
+    fn playing_name(&self) -> String {
+        if let Some(program) = &self.program {
+            return format!("program {}", program.name);
+        }
+        match &self.pomodoro {
+            Some(RunningPomodoro {
+                cycle,
+                break_sound: Some(sound),
+                ..
+            }) if cycle.phase() != Phase::Work => sound.name(),
+            _ => self.sound.name(),
+        }
+    }
+
+    // A schedule entry starting replaces whatever is playing
+    fn start_scheduled(&mut self, slot: &Slot) -> Result<(), Box<dyn std::error::Error>> {
+        self.initialize_audio()?;
+        self.stop();
+        if let Some(sound) = &slot.sound {
+            self.set_sound(sound.clone());
+        }
+        if let Some(volume) = slot.volume {
+            self.set_volume(volume);
+        }
+        self.play()?;
+        self.scheduled = true;
+        println!("Started by the schedule");
         Ok(())
     }
 
+    // Anything started by hand since the entry began keeps playing
+    fn stop_scheduled(&mut self) {
+        if self.scheduled && self.is_playing {
+            self.stop();
+            println!("Stopped by the schedule");
+        }
+    }
+
     fn stop(&mut self) {
         if let Some(sink) = self.sink.take() {
             sink.stop();
             self.is_playing = false;
-            let name = match self.sound_type {
-                SoundType::SineWave => format!("{}Hz tone", FREQUENCY_HZ as i32),
-                SoundType::WhiteNoise => "white noise".to_string(),
-                SoundType::PinkNoise => "pink noise".to_string(),
-                SoundType::BrownNoise => "brown noise".to_string(),
-            };
-            println!("Stopped {}", name);
+            println!("Stopped {} ({})", self.playing_name(), self.limiter_stats.summary());
+        }
+        self.program = None;
+        self.stop_breathing();
+        if self.pomodoro.take().is_some() {
+            println!("Stopped Pomodoro");
+        }
+    }
+
+    // Catches up with what the audio thread did on its own: a source that
+    // gave up, e.g. a file that vanished while looping, or a program moving
+    // on or finishing, or the next Pomodoro phase or the alarm being due.
+    // Returns whether anything changed.
+    fn poll(&mut self) -> bool {
+        if let Some(alarm) = &mut self.alarm
+            && alarm.due(Local::now().naive_local())
+        {
+            if let Err(e) = self.wake_up() {
+                eprintln!("Error playing the wake-up alarm: {}", e);
+                self.error = Some(e.to_string());
+            }
+            return true;
+        }
+
+        let error = self.playback_error.lock().unwrap().take();
+        if let Some(error) = error {
+            eprintln!("{}", error);
+            self.stop();
+            self.error = Some(error);
+            return true;
+        }
+
+        // The tooltip follows the breathing phase and counts down its seconds
+        let mut breathing_changed = false;
+        if let Some(breathing) = &mut self.breathing {
+            let status = breathing.breath.status(breathing.played.load(Ordering::Relaxed));
+            breathing_changed = status != breathing.status;
+            breathing.status = status;
+        }
+
+        if let Some(pomodoro) = &mut self.pomodoro {
+            let now = Instant::now();
+            let phase = pomodoro.cycle.advance(now);
+            // The tooltip counts down the minutes
+            let minutes_left = pomodoro.cycle.minutes_left(now);
+            let changed = phase.is_some() || minutes_left != pomodoro.minutes_left;
+            pomodoro.minutes_left = minutes_left;
+            if let Some(phase) = phase
+                && let Err(e) = self.pomodoro_phase_changed(phase)
+            {
+                eprintln!("Error continuing Pomodoro: {}", e);
+                self.stop();
+                self.error = Some(e.to_string());
+            }
+            return changed || breathing_changed;
+        }
+
+        let Some(program) = &mut self.program else {
+            return breathing_changed;
+        };
+        // Breathing cues keep the sink going after the last step has ended
+        let finished = program.progress.load(Ordering::Relaxed) >= program.step_names.len();
+        if finished || self.sink.as_ref().is_some_and(|sink| sink.empty()) {
+            println!("Finished program {}", program.name);
+            self.stop();
+            return true;
+        }
+        let step = program.progress.load(Ordering::Relaxed);
+        if step == program.step {
+            return breathing_changed;
+        }
+        program.step = step;
+        if let Some(name) = program.step_names.get(step) {
+            println!("Program {} moved on to {}", program.name, name);
+        }
+        true
+    }
+
+    fn tooltip(&self) -> String {
+        if let Some(error) = &self.error {
+            return error.clone();
+        }
+        if let Some(pomodoro) = &self.pomodoro {
+            return pomodoro.cycle.status(Instant::now());
+        }
+        if let Some(breathing) = &self.breathing {
+            return breathing.status.clone();
+        }
+        match &self.program {
+            Some(program) => {
+                let step = program.step.min(program.step_names.len() - 1);
+                format!(
+                    "Program {}: step {} of {} ({})",
+                    program.name,
+                    step + 1,
+                    program.step_names.len(),
+                    program.step_names[step]
+                )
+            }
+            None if self.is_playing => format!("Playing {}", self.sound.name()),
+            None => match &self.alarm {
+                Some(alarm) => format!("Audio Player - Alarm set for {}", alarm.time().format("%H:%M")),
+                None => "Audio Player - Select and play sounds".to_string(),
+            },
         }
     }
 }
@@ -323,7 +882,443 @@ fn default_run_loop_mode() -> &'static objc2_foundation::NSRunLoopMode {
     unsafe { objc2_foundation::NSDefaultRunLoopMode }
 }
 
+#[cfg(target_os = "macos")]
+fn pump_macos_events(app: &objc2_app_kit::NSApplication) {
+    use objc2_app_kit::NSEventMask;
+    use objc2_foundation::NSDate;
+
+    // Process all pending events
+    while let Some(event) = app.nextEventMatchingMask_untilDate_inMode_dequeue(
+        NSEventMask::Any,
+        Some(&NSDate::distantPast()),
+        default_run_loop_mode(),
+        true,
+    ) {
+        app.sendEvent(&event);
+    }
+}
+
+struct TrayMenu {
+    menu: Menu,
+    sound_items: Vec<(CheckMenuItem, Sound)>,
+    library_menu: Submenu,
+    library_items: Vec<(CheckMenuItem, Sound)>,
+    tone_items: Vec<CheckMenuItem>,
+    custom_tone_item: CheckMenuItem,
+    volume_menu: Submenu,
+    volume_up_item: MenuItem,
+    volume_down_item: MenuItem,
+    volume_items: Vec<(CheckMenuItem, Volume)>,
+    volume_step: i32,
+    program_items: Vec<MenuItem>,
+    pomodoro_item: CheckMenuItem,
+    breathing_item: CheckMenuItem,
+    alarm_item: CheckMenuItem,
+    favorites_menu: Submenu,
+    save_favorite_item: MenuItem,
+    favorite_items: Vec<MenuItem>,
+    play_item: MenuItem,
+    stop_item: MenuItem,
+    quit_item: MenuItem,
+}
+
+impl TrayMenu {
+    fn new(
+        volume_step: i32,
+        sound_files: &[SoundFile],
+        programs: &[ProgramConfig],
+        alarm_time: Option<NaiveTime>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let menu = Menu::new();
+
+        // Create submenu for sound selection
+        let sound_menu = Submenu::new("Select Sound", true);
+        let mut sound_items: Vec<_> = SoundType::ALL
+            .iter()
+            .map(|sound_type| {
+                let item = CheckMenuItem::new(sound_type.label(), true, false, None);
+                (item, Sound::Builtin(*sound_type))
+            })
+            .collect();
+        for (item, _) in &sound_items {
+            sound_menu.append(item)?;
+        }
+
+        // Audio files from the config follow the built-in sounds
+        sound_menu.append(&PredefinedMenuItem::separator())?;
+        for file in sound_files {
+            let item = CheckMenuItem::new(file.label(), true, false, None);
+            sound_menu.append(&item)?;
+            sound_items.push((item, Sound::File(file.clone())));
+        }
+
+        // Filled in from the sound library folder by `set_library`
+        let library_menu = Submenu::new("My Sounds", true);
+        sound_menu.append(&library_menu)?;
+
+        // Create submenu for tone shaping of the selected sound
+        let tone_menu = Submenu::new("Tone", true);
+        let tone_items: Vec<_> = TONE_PRESETS
+            .iter()
+            .map(|(name, _)| CheckMenuItem::new(*name, true, false, None))
+            .collect();
+        for item in &tone_items {
+            tone_menu.append(item)?;
+        }
+        // Checked when the settings file holds hand-tuned values; not clickable
+        let custom_tone_item = CheckMenuItem::new("Custom (settings.toml)", false, false, None);
+        tone_menu.append(&custom_tone_item)?;
+
+        // Create submenu for volume selection; the title shows the current level
+        let volume_menu = Submenu::new("Volume", true);
+        let volume_up_item = MenuItem::new(format!("Volume Up (+{}%)", volume_step), true, None);
+        let volume_down_item = MenuItem::new(format!("Volume Down (-{}%)", volume_step), true, None);
+        volume_menu.append(&volume_up_item)?;
+        volume_menu.append(&volume_down_item)?;
+        volume_menu.append(&PredefinedMenuItem::separator())?;
+
+        // Quick picks for common levels
+        let volume_items = vec![
+            (CheckMenuItem::new("Low (25%)", true, false, None), Volume::from_percent(25)),
+            (CheckMenuItem::new("Medium (50%)", true, true, None), Volume::from_percent(50)),
+            (CheckMenuItem::new("High (75%)", true, false, None), Volume::from_percent(75)),
+            (CheckMenuItem::new("Max (100%)", true, false, None), Volume::from_percent(100)),
+        ];
+        for (item, _) in &volume_items {
+            volume_menu.append(item)?;
+        }
+
+        // Timed sequences from the config's [[programs]] tables
+        let program_menu = Submenu::new("Programs", true);
+        let program_items: Vec<_> = programs.iter().map(|program| MenuItem::new(&program.name, true, None)).collect();
+        for item in &program_items {
+            program_menu.append(item)?;
+        }
+        if programs.is_empty() {
+            program_menu.append(&MenuItem::new("Add [[programs]] to config.toml", false, None))?;
+        }
+
+        // Filled in from the settings by `set_favorites`
+        let favorites_menu = Submenu::new("Favorites", true);
+        let save_favorite_item = MenuItem::new("Save Current as Favorite", true, None);
+
+        let pomodoro_item = CheckMenuItem::new("Pomodoro", true, false, None);
+        let breathing_item = CheckMenuItem::new("Breathing Exercise", true, false, None);
+        let alarm_item = match alarm_time {
+            Some(time) => CheckMenuItem::new(format!("Wake-up Alarm ({})", time.format("%H:%M")), true, false, None),
+            None => CheckMenuItem::new("Wake-up Alarm (set [alarm] time in config.toml)", false, false, None),
+        };
+
+        let play_item = MenuItem::new("Play", true, None);
+        let stop_item = MenuItem::new("Stop", false, None);
+        let quit_item = MenuItem::new("Quit", true, None);
+
+        menu.append(&favorites_menu)?;
+        menu.append(&sound_menu)?;
+        menu.append(&tone_menu)?;
+        menu.append(&volume_menu)?;
+        menu.append(&program_menu)?;
+        menu.append(&pomodoro_item)?;
+        menu.append(&breathing_item)?;
+        menu.append(&alarm_item)?;
+        menu.append(&play_item)?;
+        menu.append(&stop_item)?;
+        menu.append(&quit_item)?;
+
+        Ok(TrayMenu {
+            menu,
+            sound_items,
+            library_menu,
+            library_items: Vec::new(),
+            tone_items,
+            custom_tone_item,
+            volume_menu,
+            volume_up_item,
+            volume_down_item,
+            volume_items,
+            volume_step,
+            program_items,
+            pomodoro_item,
+            breathing_item,
+            alarm_item,
+            favorites_menu,
+            save_favorite_item,
+            favorite_items: Vec::new(),
+            play_item,
+            stop_item,
+            quit_item,
+        })
+    }
+
+    // Replaces the "My Sounds" items with the library's current files
+    fn set_library(&mut self, library: &Library) -> Result<(), Box<dyn std::error::Error>> {
+        while self.library_menu.remove_at(0).is_some() {}
+        self.library_items.clear();
+
+        if library.files().is_empty() {
+            let hint = MenuItem::new(format!("Add audio files to {}", library.dir().display()), false, None);
+            self.library_menu.append(&hint)?;
+        }
+        for file in library.files() {
+            let item = CheckMenuItem::new(file.label(), true, false, None);
+            self.library_menu.append(&item)?;
+            self.library_items.push((item, Sound::File(file.clone())));
+        }
+        Ok(())
+    }
+
+    // Rebuilds the Favorites submenu: the saved favorites, then the save item
+    fn set_favorites(&mut self, favorites: &[Favorite]) -> Result<(), Box<dyn std::error::Error>> {
+        while self.favorites_menu.remove_at(0).is_some() {}
+        self.favorite_items.clear();
+
+        for favorite in favorites {
+            let item = MenuItem::new(&favorite.name, true, None);
+            self.favorites_menu.append(&item)?;
+            self.favorite_items.push(item);
+        }
+        if !favorites.is_empty() {
+            self.favorites_menu.append(&PredefinedMenuItem::separator())?;
+        }
+        self.favorites_menu.append(&self.save_favorite_item)?;
+        Ok(())
+    }
+
+    // The sound with this `Sound::id`. Files that aren't in the menus any
+    // more are still tried, with default settings.
+    fn sound_for(&self, id: &str) -> Option<Sound> {
+        let mut sound_items = self.sound_items.iter().chain(&self.library_items);
+        if let Some((_, sound)) = sound_items.find(|(_, sound)| sound.id() == id) {
+            return Some(sound.clone());
+        }
+        let path = id.strip_prefix("file:")?;
+        Some(Sound::File(SoundFile {
+            path: path.into(),
+            settings: FileSettings::default(),
+        }))
+    }
+
+    fn action_for(&self, id: &MenuId) -> Option<Action> {
+        let mut sound_items = self.sound_items.iter().chain(&self.library_items);
+        if let Some((_, sound)) = sound_items.find(|(item, _)| item.id() == id) {
+            Some(Action::SelectSound(sound.clone()))
+        } else if let Some(index) = self.tone_items.iter().position(|item| item.id() == id) {
+            Some(Action::SelectTone(index))
+        } else if let Some((_, volume)) = self.volume_items.iter().find(|(item, _)| item.id() == id) {
+            Some(Action::SetVolume(*volume))
+        } else if id == self.volume_up_item.id() {
+            Some(Action::StepVolume(self.volume_step))
+        } else if id == self.volume_down_item.id() {
+            Some(Action::StepVolume(-self.volume_step))
+        } else if let Some(index) = self.program_items.iter().position(|item| item.id() == id) {
+            Some(Action::RunProgram(index))
+        } else if let Some(index) = self.favorite_items.iter().position(|item| item.id() == id) {
+            Some(Action::RecallFavorite(index))
+        } else if id == self.save_favorite_item.id() {
+            Some(Action::SaveFavorite)
+        } else if id == self.alarm_item.id() {
+            Some(Action::ToggleAlarm)
+        } else if id == self.pomodoro_item.id() {
+            Some(Action::TogglePomodoro)
+        } else if id == self.breathing_item.id() {
+            Some(Action::ToggleBreathing)
+        } else if id == self.play_item.id() {
+            Some(Action::Play)
+        } else if id == self.stop_item.id() {
+            Some(Action::Stop)
+        } else if id == self.quit_item.id() {
+            Some(Action::Quit)
+        } else {
+            None
+        }
+    }
+
+    // Brings check marks and enabled items in line with the audio state
+    fn sync(&self, state: &AudioState) {
+        for (item, sound) in self.sound_items.iter().chain(&self.library_items) {
+            item.set_checked(sound.id() == state.sound.id());
+            // Disable sound selection while playing
+            item.set_enabled(!state.is_playing);
+        }
+        let eq = state.settings.eq_for(&state.sound.id());
+        let mut matched = false;
+        for (item, (_, preset)) in self.tone_items.iter().zip(TONE_PRESETS.iter()) {
+            let selected = *preset == eq;
+            matched |= selected;
+            item.set_checked(selected);
+            // Tone is chosen along with the sound, before playing
+            item.set_enabled(!state.is_playing);
+        }
+        self.custom_tone_item.set_checked(!matched);
+
+        // Volume stays adjustable while playing
+        self.volume_menu.set_text(format!("Volume: {}", state.volume.label(state.volume_scale)));
+        for (item, volume) in &self.volume_items {
+            item.set_checked(*volume == state.volume);
+        }
+        self.volume_up_item.set_enabled(state.volume.percent() < 100);
+        self.volume_down_item.set_enabled(state.volume.percent() > 0);
+        self.pomodoro_item.set_checked(state.pomodoro.is_some());
+        self.breathing_item.set_checked(state.breathing.is_some());
+        self.alarm_item.set_checked(state.alarm.is_some());
+        self.play_item.set_enabled(!state.is_playing);
+        self.stop_item.set_enabled(state.is_playing);
+    }
+}
+
+// Applies an action to the audio state and updates the tray to match
+fn handle_action(action: Action, state: &mut AudioState, tray_menu: &mut TrayMenu, tray: &TrayIcon) {
+    let was_playing = state.is_playing;
+    // Anything saved below then keeps what another process wrote. Favorites
+    // are only replaced or added, so the index of a recalled one still holds.
+    reload_settings(state, tray_menu);
+
+    match action {
+        Action::SelectSound(sound) => state.set_sound(sound),
+        Action::SelectTone(preset) => state.set_tone(preset),
+        Action::SetVolume(volume) => state.set_volume(volume),
+        Action::StepVolume(delta) => state.set_volume(state.volume.step(delta)),
+        Action::Play => play(state),
+        Action::RunProgram(index) => {
+            if let Err(e) = state.run_program(index) {
+                eprintln!("Error starting program: {}", e);
+                state.error = Some(e.to_string());
+            }
+        }
+        Action::TogglePomodoro => {
+            if state.pomodoro.is_some() {
+                state.stop();
+            } else if let Err(e) = state.start_pomodoro() {
+                eprintln!("Error starting Pomodoro: {}", e);
+                state.error = Some(e.to_string());
+            }
+        }
+        Action::ToggleBreathing => {
+            if state.breathing.is_some() {
+                state.stop_breathing();
+            } else if let Err(e) = state.start_breathing() {
+                eprintln!("Error starting the breathing exercise: {}", e);
+                state.error = Some(e.to_string());
+            }
+        }
+        Action::ToggleAlarm => {
+            if let Err(e) = state.toggle_alarm() {
+                eprintln!("Error setting the wake-up alarm: {}", e);
+                state.error = Some(e.to_string());
+            }
+        }
+        Action::SaveFavorite => {
+            state.save_favorite();
+            if let Err(e) = tray_menu.set_favorites(&state.settings.favorites) {
+                eprintln!("Error updating Favorites menu: {}", e);
+            }
+        }
+        Action::RecallFavorite(index) => {
+            let Some(favorite) = state.settings.favorites.get(index) else {
+                return;
+            };
+            let result = match tray_menu.sound_for(&favorite.sound) {
+                Some(sound) => state.recall_favorite(index, sound),
+                None => Err(format!("Unknown sound \"{}\" in favorite {}", favorite.sound, favorite.name).into()),
+            };
+            if let Err(e) = result {
+                eprintln!("Error playing favorite: {}", e);
+                state.error = Some(e.to_string());
+            }
+        }
+        Action::Stop => state.stop(),
+        Action::TogglePlayback => {
+            if state.is_playing {
+                state.stop();
+            } else {
+                play(state);
+            }
+        }
+        // Handled by the event loop
+        Action::Quit => {}
+    }
+
+    update_tray(state, tray_menu, tray, was_playing);
+}
+
+// Picks up settings.toml changes made by another process, e.g.
+// --import-favorites, and shows any new favorites
+fn reload_settings(state: &mut AudioState, tray_menu: &mut TrayMenu) {
+    if state.settings.poll()
+        && let Err(e) = tray_menu.set_favorites(&state.settings.favorites)
+    {
+        eprintln!("Error updating Favorites menu: {}", e);
+    }
+}
+
+// Starts playback, keeping any error for the tray to show
+fn play(state: &mut AudioState) {
+    if let Err(e) = state.play() {
+        eprintln!("Error playing audio: {}", e);
+        state.error = Some(e.to_string());
+    }
+}
+
+fn update_tray(state: &AudioState, tray_menu: &TrayMenu, tray: &TrayIcon, was_playing: bool) {
+    tray_menu.sync(state);
+    tray.set_tooltip(Some(state.tooltip())).ok();
+
+    if state.is_playing != was_playing {
+        let icon = if state.is_playing {
+            create_playing_icon()
+        } else {
+            create_stopped_icon()
+        };
+        tray.set_icon(Some(icon)).ok();
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = match CliArgs::parse(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if cli.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+    if cli.list_favorites {
+        for favorite in Settings::load()?.favorites {
+            println!("{}", favorite.name);
+        }
+        return Ok(());
+    }
+    if let Some((name, new_name)) = &cli.rename_favorite {
+        let mut settings = Settings::load()?;
+        settings.rename_favorite(name, new_name).map_err(|e| format!("Couldn't rename favorite: {}", e))?;
+        settings.save()?;
+        println!("Renamed favorite {} to {}", name, new_name.trim());
+        return Ok(());
+    }
+    if let Some(path) = &cli.export_favorites {
+        let favorites = Settings::load()?.favorites;
+        std::fs::write(path, preset::export(&favorites)?)?;
+        println!("Exported {} favorites to {}", favorites.len(), path.display());
+        return Ok(());
+    }
+    if let Some(path) = &cli.import_favorites {
+        let contents = std::fs::read_to_string(path)?;
+        let favorites = preset::import(&contents).map_err(|e| format!("Couldn't import {}: {}", path.display(), e))?;
+        let mut settings = Settings::load()?;
+        let names: Vec<String> = favorites.iter().map(|favorite| favorite.name.clone()).collect();
+        for favorite in favorites {
+            settings.save_favorite(favorite);
+        }
+        settings.save()?;
+        for name in names {
+            println!("Imported {}", name);
+        }
+        return Ok(());
+    }
+
     println!("Starting macOS Audio Tray App...");
 
     // On macOS, we MUST initialize NSApplication BEFORE creating any tray icons
@@ -342,297 +1337,177 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
       
     }
 
-    let audio_state = Arc::new(Mutex::new(AudioState::new()));
-
-    let menu = Menu::new();
-
-    // Create submenu for sound selection
-    let sound_menu = Submenu::new("Select Sound", true);
-    let sine_item = CheckMenuItem::new(&format!("{}Hz Tone", FREQUENCY_HZ as i32), true, true, None);
-    let white_noise_item = CheckMenuItem::new("White Noise", true, false, None);
-    let pink_noise_item = CheckMenuItem::new("Pink Noise", true, false, None);
-    let brown_noise_item = CheckMenuItem::new("Brown Noise", true, false, None);
-
-    sound_menu.append(&sine_item)?;
-    sound_menu.append(&white_noise_item)?;
-    sound_menu.append(&pink_noise_item)?;
-    sound_menu.append(&brown_noise_item)?;
-
-    // Create submenu for volume selection
-    let volume_menu = Submenu::new("Volume", true);
-    let vol_low_item = CheckMenuItem::new("Low (25%)", true, false, None);
-    let vol_medium_item = CheckMenuItem::new("Medium (50%)", true, true, None);
-    let vol_high_item = CheckMenuItem::new("High (75%)", true, false, None);
-    let vol_max_item = CheckMenuItem::new("Max (100%)", true, false, None);
-
-    volume_menu.append(&vol_low_item)?;
-    volume_menu.append(&vol_medium_item)?;
-    volume_menu.append(&vol_high_item)?;
-    volume_menu.append(&vol_max_item)?;
+    let config = Config::load();
+
+    let mut initial_state = AudioState::new();
+    initial_state.volume_scale = config.volume.scale;
+    initial_state.sound_params = config.sounds.clone();
+    initial_state.modulation = config.modulation.clone();
+    initial_state.positions = config.position.clone();
+    initial_state.programs = config.programs.clone();
+    initial_state.pomodoro_config = config.pomodoro.clone();
+    initial_state.alarm_config = config.alarm.clone();
+    initial_state.breathing_config = config.breathing.clone();
+    // A configured alarm starts out switched on
+    let alarm_time = match config.alarm.time() {
+        Ok(time) => time,
+        Err(e) => {
+            eprintln!("Error in config: {}", e);
+            None
+        }
+    };
+    if alarm_time.is_some()
+        && let Err(e) = initial_state.toggle_alarm()
+    {
+        eprintln!("Error in config: {}", e);
+    }
+    if let Some(volume) = cli.volume {
+        initial_state.set_volume(volume);
+    }
 
-    let play_item = MenuItem::new("Play", true, None);
-    let stop_item = MenuItem::new("Stop", false, None);
-    let quit_item = MenuItem::new("Quit", true, None);
+    let mut library = config.library.dir().map(Library::new);
+    let mut last_library_poll = Instant::now();
+    let mut last_settings_poll = Instant::now();
+
+    let slots = config
+        .schedule
+        .iter()
+        .filter_map(|entry| match entry.slot() {
+            Ok(slot) => Some(slot),
+            Err(e) => {
+                eprintln!("Error in config: {}", e);
+                None
+            }
+        })
+        .collect();
+    let mut scheduler = Scheduler::new(slots, LocalClock);
+    let mut last_schedule_poll: Option<Instant> = None;
+
+    let mut tray_menu = TrayMenu::new(config.volume.step, &config.files, &config.programs, alarm_time)?;
+    if let Some(library) = &library {
+        tray_menu.set_library(library)?;
+    }
+    tray_menu.set_favorites(&initial_state.settings.favorites)?;
+    tray_menu.sync(&initial_state);
 
-    menu.append(&sound_menu)?;
-    menu.append(&volume_menu)?;
-    menu.append(&play_item)?;
-    menu.append(&stop_item)?;
-    menu.append(&quit_item)?;
+    let audio_state = Arc::new(Mutex::new(initial_state));
 
     let icon = create_stopped_icon();
 
     // Now it's safe to create the tray icon after NSApplication is initialized
     let tray = TrayIconBuilder::new()
-        .with_menu(Box::new(menu))
+        .with_menu(Box::new(tray_menu.menu.clone()))
         .with_tooltip("Audio Player - Select and play sounds")
         .with_icon(icon)
         .build()?;
 
+    // Hotkeys are optional; the tray menu keeps working if the OS refuses them
+    let hotkeys = match Hotkeys::register(&config.hotkeys, config.volume.step) {
+        Ok(hotkeys) => Some(hotkeys),
+        Err(e) => {
+            eprintln!("Global hotkeys unavailable: {}", e);
+            None
+        }
+    };
+
+    if let Some(name) = &cli.favorite {
+        let mut state = audio_state.lock().unwrap();
+        match state.settings.favorite(name) {
+            Some(index) => handle_action(Action::RecallFavorite(index), &mut state, &mut tray_menu, &tray),
+            None => eprintln!("No favorite named \"{}\" (see --list-favorites)", name),
+        }
+    }
+
     println!("Tray icon created. Look for it in your menu bar!");
     println!("Use the menu to select a sound and play it.");
 
     let menu_channel = MenuEvent::receiver();
+    let hotkey_channel = GlobalHotKeyEvent::receiver();
 
     #[cfg(target_os = "macos")]
-    {
-        // On macOS, we need to pump the event loop
-        use std::time::Duration;
+    let app = {
         use objc2_app_kit::NSApplication;
         use objc2_foundation::MainThreadMarker;
 
         let mtm = MainThreadMarker::new().unwrap();
-        let app = NSApplication::sharedApplication(mtm);
+        NSApplication::sharedApplication(mtm)
+    };
+
+    loop {
+        // On macOS, we need to pump the event loop
+        #[cfg(target_os = "macos")]
+        pump_macos_events(&app);
+
+        let mut actions = Vec::new();
+
+        // Check for menu events
+        while let Ok(event) = menu_channel.try_recv() {
+            actions.extend(tray_menu.action_for(event.id()));
+        }
 
-        // Process events in a loop
-        loop {
-            // Process pending macOS events
-            
-            use objc2_app_kit::NSEventMask;
-            use objc2_foundation::NSDate;
-
-            // Process all pending events
-            while let Some(event) = app.nextEventMatchingMask_untilDate_inMode_dequeue(
-                NSEventMask::Any,
-                    Some(&NSDate::distantPast()),
-                    default_run_loop_mode(),
-                true,
-            ) {
-                app.sendEvent(&event);
+        // Check for global hotkey presses (ignore the matching key releases)
+        while let Ok(event) = hotkey_channel.try_recv() {
+            if event.state() != HotKeyState::Pressed {
+                continue;
             }
-            
-
-            // Check for menu events
-            if let Ok(event) = menu_channel.try_recv() {
-                let event_id = event.id;
-
-                if event_id == sine_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    state.set_sound_type(SoundType::SineWave);
-                    sine_item.set_checked(true);
-                    white_noise_item.set_checked(false);
-                    pink_noise_item.set_checked(false);
-                    brown_noise_item.set_checked(false);
-                } else if event_id == white_noise_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    state.set_sound_type(SoundType::WhiteNoise);
-                    sine_item.set_checked(false);
-                    white_noise_item.set_checked(true);
-                    pink_noise_item.set_checked(false);
-                    brown_noise_item.set_checked(false);
-                } else if event_id == pink_noise_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    state.set_sound_type(SoundType::PinkNoise);
-                    sine_item.set_checked(false);
-                    white_noise_item.set_checked(false);
-                    pink_noise_item.set_checked(true);
-                    brown_noise_item.set_checked(false);
-                } else if event_id == brown_noise_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    state.set_sound_type(SoundType::BrownNoise);
-                    sine_item.set_checked(false);
-                    white_noise_item.set_checked(false);
-                    pink_noise_item.set_checked(false);
-                    brown_noise_item.set_checked(true);
-                } else if event_id == vol_low_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    state.set_volume(0.25);
-                    vol_low_item.set_checked(true);
-                    vol_medium_item.set_checked(false);
-                    vol_high_item.set_checked(false);
-                    vol_max_item.set_checked(false);
-                } else if event_id == vol_medium_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    state.set_volume(0.5);
-                    vol_low_item.set_checked(false);
-                    vol_medium_item.set_checked(true);
-                    vol_high_item.set_checked(false);
-                    vol_max_item.set_checked(false);
-                } else if event_id == vol_high_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    state.set_volume(0.75);
-                    vol_low_item.set_checked(false);
-                    vol_medium_item.set_checked(false);
-                    vol_high_item.set_checked(true);
-                    vol_max_item.set_checked(false);
-                } else if event_id == vol_max_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    state.set_volume(1.0);
-                    vol_low_item.set_checked(false);
-                    vol_medium_item.set_checked(false);
-                    vol_high_item.set_checked(false);
-                    vol_max_item.set_checked(true);
-                } else if event_id == play_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    if let Err(e) = state.play() {
-                        eprintln!("Error playing audio: {}", e);
-                    } else {
-                        play_item.set_enabled(false);
-                        stop_item.set_enabled(true);
-                        // Disable sound selection while playing
-                        sine_item.set_enabled(false);
-                        white_noise_item.set_enabled(false);
-                        pink_noise_item.set_enabled(false);
-                        brown_noise_item.set_enabled(false);
-                        // Disable volume adjustment while playing
-                        vol_low_item.set_enabled(false);
-                        vol_medium_item.set_enabled(false);
-                        vol_high_item.set_enabled(false);
-                        vol_max_item.set_enabled(false);
-                        tray.set_icon(Some(create_playing_icon())).ok();
-                    }
-                } else if event_id == stop_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    state.stop();
-                    play_item.set_enabled(true);
-                    stop_item.set_enabled(false);
-                    // Re-enable sound selection when stopped
-                    sine_item.set_enabled(true);
-                    white_noise_item.set_enabled(true);
-                    pink_noise_item.set_enabled(true);
-                    brown_noise_item.set_enabled(true);
-                    // Re-enable volume adjustment when stopped
-                    vol_low_item.set_enabled(true);
-                    vol_medium_item.set_enabled(true);
-                    vol_high_item.set_enabled(true);
-                    vol_max_item.set_enabled(true);
-                    tray.set_icon(Some(create_stopped_icon())).ok();
-                } else if event_id == quit_item.id() {
-                    println!("Quitting application...");
-                    break;
-                }
+            if let Some(hotkeys) = &hotkeys {
+                actions.extend(hotkeys.action_for(event.id()));
+            }
+        }
+
+        for action in actions {
+            if action == Action::Quit {
+                println!("Quitting application...");
+                return Ok(());
             }
 
-            std::thread::sleep(Duration::from_millis(10));
+            let mut state = audio_state.lock().unwrap();
+            handle_action(action, &mut state, &mut tray_menu, &tray);
         }
-    }
 
-    #[cfg(not(target_os = "macos"))]
-    {
-        loop {
-            if let Ok(event) = menu_channel.try_recv() {
-                let event_id = event.id;
-
-                if event_id == sine_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    state.set_sound_type(SoundType::SineWave);
-                    sine_item.set_checked(true);
-                    white_noise_item.set_checked(false);
-                    pink_noise_item.set_checked(false);
-                    brown_noise_item.set_checked(false);
-                } else if event_id == white_noise_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    state.set_sound_type(SoundType::WhiteNoise);
-                    sine_item.set_checked(false);
-                    white_noise_item.set_checked(true);
-                    pink_noise_item.set_checked(false);
-                    brown_noise_item.set_checked(false);
-                } else if event_id == pink_noise_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    state.set_sound_type(SoundType::PinkNoise);
-                    sine_item.set_checked(false);
-                    white_noise_item.set_checked(false);
-                    pink_noise_item.set_checked(true);
-                    brown_noise_item.set_checked(false);
-                } else if event_id == brown_noise_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    state.set_sound_type(SoundType::BrownNoise);
-                    sine_item.set_checked(false);
-                    white_noise_item.set_checked(false);
-                    pink_noise_item.set_checked(false);
-                    brown_noise_item.set_checked(true);
-                } else if event_id == vol_low_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    state.set_volume(0.25);
-                    vol_low_item.set_checked(true);
-                    vol_medium_item.set_checked(false);
-                    vol_high_item.set_checked(false);
-                    vol_max_item.set_checked(false);
-                } else if event_id == vol_medium_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    state.set_volume(0.5);
-                    vol_low_item.set_checked(false);
-                    vol_medium_item.set_checked(true);
-                    vol_high_item.set_checked(false);
-                    vol_max_item.set_checked(false);
-                } else if event_id == vol_high_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    state.set_volume(0.75);
-                    vol_low_item.set_checked(false);
-                    vol_medium_item.set_checked(false);
-                    vol_high_item.set_checked(true);
-                    vol_max_item.set_checked(false);
-                } else if event_id == vol_max_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    state.set_volume(1.0);
-                    vol_low_item.set_checked(false);
-                    vol_medium_item.set_checked(false);
-                    vol_high_item.set_checked(false);
-                    vol_max_item.set_checked(true);
-                } else if event_id == play_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    if let Err(e) = state.play() {
-                        eprintln!("Error playing audio: {}", e);
-                    } else {
-                        play_item.set_enabled(false);
-                        stop_item.set_enabled(true);
-                        // Disable sound selection while playing
-                        sine_item.set_enabled(false);
-                        white_noise_item.set_enabled(false);
-                        pink_noise_item.set_enabled(false);
-                        brown_noise_item.set_enabled(false);
-                        // Disable volume adjustment while playing
-                        vol_low_item.set_enabled(false);
-                        vol_medium_item.set_enabled(false);
-                        vol_high_item.set_enabled(false);
-                        vol_max_item.set_enabled(false);
-                        tray.set_icon(Some(create_playing_icon())).ok();
+        let mut state = audio_state.lock().unwrap();
+        if state.poll() {
+            update_tray(&state, &tray_menu, &tray, true);
+        }
+
+        if !scheduler.is_empty() && last_schedule_poll.is_none_or(|poll| poll.elapsed() >= SCHEDULE_POLL_INTERVAL) {
+            last_schedule_poll = Some(Instant::now());
+            let was_playing = state.is_playing;
+            match scheduler.poll() {
+                Some(Change::Start(slot)) => {
+                    if let Err(e) = state.start_scheduled(slot) {
+                        eprintln!("Error starting scheduled playback: {}", e);
+                        state.error = Some(e.to_string());
                     }
-                } else if event_id == stop_item.id() {
-                    let mut state = audio_state.lock().unwrap();
-                    state.stop();
-                    play_item.set_enabled(true);
-                    stop_item.set_enabled(false);
-                    // Re-enable sound selection when stopped
-                    sine_item.set_enabled(true);
-                    white_noise_item.set_enabled(true);
-                    pink_noise_item.set_enabled(true);
-                    brown_noise_item.set_enabled(true);
-                    // Re-enable volume adjustment when stopped
-                    vol_low_item.set_enabled(true);
-                    vol_medium_item.set_enabled(true);
-                    vol_high_item.set_enabled(true);
-                    vol_max_item.set_enabled(true);
-                    tray.set_icon(Some(create_stopped_icon())).ok();
-                } else if event_id == quit_item.id() {
-                    println!("Quitting application...");
-                    break;
+                    update_tray(&state, &tray_menu, &tray, was_playing);
                 }
+                Some(Change::Stop) => {
+                    state.stop_scheduled();
+                    update_tray(&state, &tray_menu, &tray, was_playing);
+                }
+                None => {}
             }
+        }
 
-            std::thread::sleep(std::time::Duration::from_millis(100));
+        if last_settings_poll.elapsed() >= SETTINGS_POLL_INTERVAL {
+            last_settings_poll = Instant::now();
+            reload_settings(&mut state, &mut tray_menu);
         }
-    }
 
-    Ok(())
+        if last_library_poll.elapsed() >= LIBRARY_POLL_INTERVAL {
+            last_library_poll = Instant::now();
+            if let Some(library) = &mut library
+                && library.poll()
+            {
+                if let Err(e) = tray_menu.set_library(library) {
+                    eprintln!("Error updating My Sounds menu: {}", e);
+                }
+                state.library_changed(library);
+                tray_menu.sync(&state);
+            }
+        }
+        drop(state);
+
+        std::thread::sleep(EVENT_POLL_INTERVAL);
+    }
 }
@@ -0,0 +1,157 @@
+use crate::noise::SAMPLE_RATE;
+use rodio::Source;
+use rodio::source::UniformSourceIterator;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Every input is converted to this format, so inputs can be mixed sample by sample
+const CHANNELS: u16 = 2;
+// New inputs are picked up this often, in frames (about 1 ms)
+const PICK_UP_FRAMES: u32 = 48;
+
+type Input = Box<dyn Source<Item = f32> + Send>;
+
+// Sources waiting to join a `Mixer`. Shared with the audio thread, so cues can
+// be added to what's already playing.
+#[derive(Clone, Default)]
+pub struct MixerInputs(Arc<Mutex<Vec<Input>>>);
+
+impl MixerInputs {
+    pub fn add(&self, source: impl Source<Item = f32> + Send + 'static) {
+        self.0.lock().unwrap().push(Box::new(source));
+    }
+}
+
+// Sums any number of sources, each with its own channel count and sample
+// rate, into one stereo stream, so a single limiter sees everything that
+// plays. Ends once every source has ended.
+pub struct Mixer {
+    inputs: MixerInputs,
+    sources: Vec<UniformSourceIterator<Input>>,
+    frame: u32,
+    channel: u16,
+    // The other channel of the current frame
+    right: f32,
+}
+
+impl Mixer {
+    pub fn new(inputs: MixerInputs) -> Self {
+        let mut mixer = Mixer {
+            inputs,
+            sources: Vec::new(),
+            frame: 0,
+            channel: 0,
+            right: 0.0,
+        };
+        mixer.pick_up();
+        mixer
+    }
+
+    fn pick_up(&mut self) {
+        let added: Vec<Input> = self.inputs.0.lock().unwrap().drain(..).collect();
+        self.sources
+            .extend(added.into_iter().map(|source| UniformSourceIterator::new(source, CHANNELS, SAMPLE_RATE)));
+    }
+
+    // Left of the next frame; keeps the right for the following sample
+    fn start_frame(&mut self) -> Option<f32> {
+        if self.frame.is_multiple_of(PICK_UP_FRAMES) || self.sources.is_empty() {
+            self.pick_up();
+        }
+        self.frame = self.frame.wrapping_add(1);
+        let mut left = 0.0;
+        let mut right = 0.0;
+        self.sources.retain_mut(|source| match (source.next(), source.next()) {
+            (Some(l), Some(r)) => {
+                left += l;
+                right += r;
+                true
+            }
+            _ => false,
+        });
+        if self.sources.is_empty() {
+            return None;
+        }
+        self.right = right;
+        Some(left)
+    }
+}
+
+impl Iterator for Mixer {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = if self.channel == 0 { self.start_frame()? } else { self.right };
+        self.channel = (self.channel + 1) % CHANNELS;
+        Some(sample)
+    }
+}
+
+impl Source for Mixer {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        CHANNELS
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bell::Cue;
+    use crate::noise::PinkNoise;
+    use crate::ocean::{Ocean, OceanParams};
+    use rodio::source::SineWave;
+
+    #[test]
+    fn mixes_mono_and_stereo_sources() {
+        let ocean = OceanParams { seed: Some(1), ..OceanParams::default() };
+        let inputs = MixerInputs::default();
+        inputs.add(PinkNoise::with_seed(1).take_duration(Duration::from_secs(1)));
+        inputs.add(Ocean::new(ocean).take_duration(Duration::from_secs(2)));
+        let mut mixer = Mixer::new(inputs);
+        assert_eq!((mixer.channels(), mixer.sample_rate()), (2, SAMPLE_RATE));
+
+        // The mono noise is in both channels, so for the first second left
+        // and right differ only by the ocean's stereo difference
+        let mut noise = PinkNoise::with_seed(1);
+        let mut ocean = Ocean::new(ocean);
+        for _ in 0..SAMPLE_RATE {
+            let n = noise.next().unwrap();
+            let (l, r) = (ocean.next().unwrap(), ocean.next().unwrap());
+            assert!((mixer.next().unwrap() - (n + l)).abs() < 1e-6);
+            assert!((mixer.next().unwrap() - (n + r)).abs() < 1e-6);
+        }
+        // Then just the ocean until it ends too
+        let rest = mixer.count();
+        assert!(rest.abs_diff(2 * SAMPLE_RATE as usize) < 16, "{}", rest);
+    }
+
+    #[test]
+    fn picks_up_sources_added_while_playing() {
+        let inputs = MixerInputs::default();
+        inputs.add(SineWave::new(440.0).take_duration(Duration::from_secs(1)));
+        let mut mixer = Mixer::new(inputs.clone());
+        let before: Vec<f32> = mixer.by_ref().take(SAMPLE_RATE as usize).collect();
+
+        inputs.add(Cue::chime(true));
+        let after: Vec<f32> = mixer.by_ref().take(SAMPLE_RATE as usize).collect();
+        let sine = SineWave::new(440.0).take_duration(Duration::from_secs(1));
+        let plain: Vec<f32> = UniformSourceIterator::new(sine, CHANNELS, SAMPLE_RATE).collect();
+        assert_eq!(before, plain[..before.len()]);
+        assert!(after.iter().zip(&plain[before.len()..]).any(|(mixed, sine)| (mixed - sine).abs() > 0.01));
+
+        // The chime keeps the mixer going after the sine has ended
+        assert!(mixer.count() > 0);
+    }
+}
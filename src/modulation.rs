@@ -0,0 +1,293 @@
+use crate::eq::Biquad;
+use crate::pan;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rodio::Source;
+use rodio::source::UniformSourceIterator;
+use serde::Deserialize;
+use std::f32::consts::{PI, TAU};
+use std::time::Duration;
+
+// A swept filter gets new coefficients this often, in frames; the LFOs are
+// far too slow for the steps to be heard
+const RETUNE_FRAMES: u32 = 32;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Shape {
+    #[default]
+    Sine,
+    Triangle,
+    // Wanders to a new random level every cycle, easing between them
+    Random,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    #[default]
+    Gain,
+    // A low-pass filter swept around `cutoff_hz`
+    Cutoff,
+    // Left and right; the sound is made stereo
+    Pan,
+}
+
+// One slow LFO on a sound, from a [[modulation.<sound>]] table in the config
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct LfoConfig {
+    pub target: Target,
+    pub shape: Shape,
+    // Cycles per second; 0.1 is one slow breath every ten seconds
+    pub rate_hz: f32,
+    // How far it swings. Gain: the fraction the level dips by at the bottom,
+    // 0 to 1. Cutoff: octaves either side of `cutoff_hz`. Pan: how far from
+    // the center, 0 to 1.
+    pub depth: f32,
+    // Center of the cutoff sweep
+    pub cutoff_hz: f32,
+    // Fixed seed for the random shape; random when unset
+    pub seed: Option<u64>,
+}
+
+impl Default for LfoConfig {
+    fn default() -> Self {
+        LfoConfig {
+            target: Target::Gain,
+            shape: Shape::Sine,
+            rate_hz: 0.1,
+            depth: 0.5,
+            cutoff_hz: 2000.0,
+            seed: None,
+        }
+    }
+}
+
+// A low-frequency oscillator between -1 and 1. Every shape starts at 1, so a
+// gain swell starts at full level rather than fading in.
+pub struct Lfo {
+    shape: Shape,
+    // Position in the cycle, from 0 to 1
+    phase: f32,
+    step: f32,
+    rng: StdRng,
+    // The random shape eases from `from` to `to` over each cycle
+    from: f32,
+    to: f32,
+}
+
+impl Lfo {
+    pub fn new(config: &LfoConfig, sample_rate: u32) -> Self {
+        let mut rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let to = rng.gen_range(-1.0..=1.0);
+        Lfo {
+            shape: config.shape,
+            phase: 0.0,
+            step: config.rate_hz.clamp(0.001, 20.0) / sample_rate as f32,
+            rng,
+            from: 1.0,
+            to,
+        }
+    }
+
+    fn value(&self) -> f32 {
+        match self.shape {
+            Shape::Sine => (TAU * self.phase).cos(),
+            Shape::Triangle => 4.0 * (self.phase - 0.5).abs() - 1.0,
+            // Cosine easing, so the level never changes direction abruptly
+            Shape::Random => self.from + (self.to - self.from) * (1.0 - (PI * self.phase).cos()) / 2.0,
+        }
+    }
+
+    // Value for this frame, then moves on by one frame
+    pub fn advance(&mut self) -> f32 {
+        let value = self.value();
+        self.phase += self.step;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+            self.from = self.to;
+            self.to = self.rng.gen_range(-1.0..=1.0);
+        }
+        value
+    }
+}
+
+// Applies an LFO to the gain, a low-pass cutoff or the pan of any source.
+// Works a frame at a time, so every channel is changed together.
+pub struct Modulator<S> {
+    source: S,
+    lfo: Lfo,
+    target: Target,
+    depth: f32,
+    cutoff_hz: f32,
+    sample_rate: f32,
+    // One filter per channel, only used for the cutoff
+    filters: Vec<Biquad>,
+    // Gain for each channel in the current frame
+    gains: Vec<f32>,
+    frame: u32,
+    channel: usize,
+}
+
+impl<S> Modulator<S>
+where
+    S: Source<Item = f32>,
+{
+    // Pan needs two channels; use `modulate` to convert the source first
+    pub fn new(source: S, config: &LfoConfig) -> Self {
+        let channels = source.channels() as usize;
+        let sample_rate = source.sample_rate() as f32;
+        let depth = match config.target {
+            Target::Gain | Target::Pan => config.depth.clamp(0.0, 1.0),
+            Target::Cutoff => config.depth.clamp(0.0, 4.0),
+        };
+        let cutoff_hz = config.cutoff_hz.clamp(20.0, sample_rate * 0.45);
+        let filters = match config.target {
+            Target::Cutoff => vec![Biquad::low_pass(cutoff_hz, sample_rate); channels],
+            Target::Gain | Target::Pan => Vec::new(),
+        };
+        Modulator {
+            lfo: Lfo::new(config, source.sample_rate()),
+            source,
+            target: config.target,
+            depth,
+            cutoff_hz,
+            sample_rate,
+            filters,
+            gains: vec![1.0; channels],
+            frame: 0,
+            channel: 0,
+        }
+    }
+
+    fn start_frame(&mut self) {
+        let value = self.lfo.advance();
+        match self.target {
+            // From full level at the top down to 1 - depth at the bottom
+            Target::Gain => self.gains.fill(1.0 - self.depth * (1.0 - value) / 2.0),
+            Target::Pan if self.gains.len() == 2 => self.gains.copy_from_slice(&pan::gains(self.depth * value)),
+            Target::Pan => {}
+            Target::Cutoff => {
+                if self.frame.is_multiple_of(RETUNE_FRAMES) {
+                    let freq = (self.cutoff_hz * 2f32.powf(self.depth * value)).clamp(20.0, self.sample_rate * 0.45);
+                    let tuned = Biquad::low_pass(freq, self.sample_rate);
+                    self.filters.iter_mut().for_each(|filter| filter.retune(&tuned));
+                }
+            }
+        }
+        self.frame = self.frame.wrapping_add(1);
+    }
+}
+
+impl<S> Iterator for Modulator<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.channel == 0 {
+            self.start_frame();
+        }
+        let channel = self.channel;
+        self.channel = (self.channel + 1) % self.gains.len();
+        let mut sample = self.source.next()?;
+        if let Some(filter) = self.filters.get_mut(channel) {
+            sample = filter.process(sample);
+        }
+        Some(sample * self.gains[channel])
+    }
+}
+
+impl<S> Source for Modulator<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_span_len(&self) -> Option<usize> {
+        self.source.current_span_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+// Wraps a source in one modulator per LFO, in order. Panning makes the
+// source stereo first.
+pub fn modulate(
+    mut source: Box<dyn Source<Item = f32> + Send>,
+    lfos: &[LfoConfig],
+) -> Box<dyn Source<Item = f32> + Send> {
+    for lfo in lfos {
+        if lfo.target == Target::Pan && source.channels() != 2 {
+            let sample_rate = source.sample_rate();
+            source = Box::new(UniformSourceIterator::new(source, 2, sample_rate));
+        }
+        source = Box::new(Modulator::new(source, lfo));
+    }
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::{PinkNoise, SAMPLE_RATE};
+
+    // RMS of each whole second
+    fn levels(source: impl Iterator<Item = f32>, seconds: usize) -> Vec<f32> {
+        let samples: Vec<f32> = source.take(seconds * SAMPLE_RATE as usize).collect();
+        samples
+            .chunks(SAMPLE_RATE as usize)
+            .map(|second| (second.iter().map(|s| s * s).sum::<f32>() / second.len() as f32).sqrt())
+            .collect()
+    }
+
+    #[test]
+    fn shapes_stay_in_range_and_repeat() {
+        for shape in [Shape::Sine, Shape::Triangle, Shape::Random] {
+            let config = LfoConfig { shape, rate_hz: 1.0, seed: Some(3), ..LfoConfig::default() };
+            let mut lfo = Lfo::new(&config, 1000);
+            let values: Vec<f32> = (0..3000).map(|_| lfo.advance()).collect();
+            assert_eq!(values[0], 1.0);
+            assert!(values.iter().all(|value| (-1.0..=1.0).contains(value)), "{:?} out of range", shape);
+            // Smooth enough that no step between frames is large
+            assert!(values.windows(2).all(|pair| (pair[1] - pair[0]).abs() < 0.02), "{:?} jumps", shape);
+            if shape != Shape::Random {
+                assert!((values[500] + 1.0).abs() < 1e-3 && (values[1000] - 1.0).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn swells_the_gain_and_pans() {
+        // A 0.1 Hz breathing swell on pink noise: full at the start, down by
+        // half (6 dB) five seconds in, back up at ten
+        let config = LfoConfig { depth: 0.5, ..LfoConfig::default() };
+        let swell = levels(Modulator::new(PinkNoise::with_seed(1), &config), 11);
+        let plain = levels(PinkNoise::with_seed(1), 11);
+        let ratio: Vec<f32> = swell.iter().zip(&plain).map(|(swell, plain)| swell / plain).collect();
+        assert!(ratio[0] > 0.95 && ratio[10] > 0.95);
+        assert!((ratio[4] - 0.52).abs() < 0.03, "{:?}", ratio);
+
+        // Panned hard right at the start, hard left half a cycle later
+        let pan = LfoConfig { target: Target::Pan, depth: 1.0, rate_hz: 1.0, ..LfoConfig::default() };
+        let panned = modulate(Box::new(PinkNoise::with_seed(1)), &[pan]);
+        assert_eq!(panned.channels(), 2);
+        let samples: Vec<f32> = panned.take(2 * SAMPLE_RATE as usize).collect();
+        assert!(samples[0].abs() < 1e-6 && samples[1] != 0.0);
+        let half = SAMPLE_RATE as usize;
+        assert!(samples[half + 1].abs() < 1e-3 && samples[half] != 0.0);
+    }
+}
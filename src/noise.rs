@@ -0,0 +1,318 @@
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rodio::Source;
+use serde::Deserialize;
+
+// Sample rate shared by all the generators
+pub const SAMPLE_RATE: u32 = 48000;
+
+// White noise generator
+pub struct WhiteNoise {
+    rng: StdRng,
+}
+
+impl WhiteNoise {
+    pub fn new() -> Self {
+        WhiteNoise {
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    // Produces the same sequence every time for a given seed
+    pub fn with_seed(seed: u64) -> Self {
+        WhiteNoise {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Iterator for WhiteNoise {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.rng.gen_range(-1.0..1.0))
+    }
+}
+
+impl Source for WhiteNoise {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
+
+// Pink noise generator using Paul Kellett's algorithm
+pub struct PinkNoise {
+    white_noise: WhiteNoise,
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    b3: f32,
+    b4: f32,
+    b5: f32,
+    b6: f32,
+}
+
+impl PinkNoise {
+    pub fn new() -> Self {
+        Self::from_white(WhiteNoise::new())
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self::from_white(WhiteNoise::with_seed(seed))
+    }
+
+    fn from_white(white_noise: WhiteNoise) -> Self {
+        PinkNoise {
+            white_noise,
+            b0: 0.0,
+            b1: 0.0,
+            b2: 0.0,
+            b3: 0.0,
+            b4: 0.0,
+            b5: 0.0,
+            b6: 0.0,
+        }
+    }
+}
+
+// Kellett's published coefficients are kept verbatim
+#[allow(clippy::excessive_precision)]
+impl Iterator for PinkNoise {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let white = self.white_noise.next()?;
+
+        self.b0 = 0.99886 * self.b0 + white * 0.0555179;
+        self.b1 = 0.99332 * self.b1 + white * 0.0750759;
+        self.b2 = 0.96900 * self.b2 + white * 0.1538520;
+        self.b3 = 0.86650 * self.b3 + white * 0.3104856;
+        self.b4 = 0.55000 * self.b4 + white * 0.5329522;
+        self.b5 = -0.7616 * self.b5 - white * 0.0168980;
+
+        let pink = self.b0 + self.b1 + self.b2 + self.b3 + self.b4 + self.b5 + self.b6 + white * 0.5362;
+        self.b6 = white * 0.115926;
+
+        Some(pink * 0.11) // Scale down to reasonable volume
+    }
+}
+
+impl Source for PinkNoise {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
+
+// Brown noise generator using 1/f² spectrum (leaky integrator)
+// This produces a smoother, more natural brown noise than simple random walk
+pub struct BrownNoise {
+    white_noise: WhiteNoise,
+    integrator1: f32,
+    integrator2: f32,
+}
+
+impl BrownNoise {
+    pub fn new() -> Self {
+        Self::from_white(WhiteNoise::new())
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self::from_white(WhiteNoise::with_seed(seed))
+    }
+
+    fn from_white(white_noise: WhiteNoise) -> Self {
+        BrownNoise {
+            white_noise,
+            integrator1: 0.0,
+            integrator2: 0.0,
+        }
+    }
+}
+
+impl Iterator for BrownNoise {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let white = self.white_noise.next()?;
+        
+        // Two-stage leaky integrator to create 1/f² spectrum
+        // First integrator with time constant ~0.97
+        self.integrator1 = 0.97 * self.integrator1 + 0.03 * white;
+        // Second integrator with time constant ~0.94
+        self.integrator2 = 0.94 * self.integrator2 + 0.06 * white;
+        
+        // Combine the two integrators and scale to reasonable volume
+        Some((self.integrator1 + self.integrator2) * 0.5)
+    }
+}
+
+impl Source for BrownNoise {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct NoiseParams {
+    // Play white, pink and brown noise in stereo, from a separate generator
+    // for each side, so it sounds around the head rather than inside it
+    pub stereo: bool,
+    // How different the two sides are: 0.0 is the same on both, like mono,
+    // 1.0 is completely independent
+    pub width: f32,
+}
+
+impl Default for NoiseParams {
+    fn default() -> Self {
+        NoiseParams {
+            stereo: false,
+            width: 1.0,
+        }
+    }
+}
+
+// Two generators of the same color mixed into left and right as sum and
+// difference. The correlation between the sides is cos(width * 90°), and
+// each side has the same power as one generator at every width, so changing
+// it doesn't change the loudness.
+pub struct StereoNoise<N> {
+    first: N,
+    second: N,
+    mid: f32,
+    side: f32,
+    // The right sample of the current frame, once the left has gone out
+    right: Option<f32>,
+}
+
+impl<N> StereoNoise<N>
+where
+    N: Iterator<Item = f32>,
+{
+    // The generators need their own random streams, e.g. two `new()`s
+    pub fn new(first: N, second: N, width: f32) -> Self {
+        let angle = width.clamp(0.0, 1.0) * std::f32::consts::FRAC_PI_4;
+        StereoNoise {
+            first,
+            second,
+            mid: angle.cos(),
+            side: angle.sin(),
+            right: None,
+        }
+    }
+}
+
+impl<N> Iterator for StereoNoise<N>
+where
+    N: Iterator<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(right) = self.right.take() {
+            return Some(right);
+        }
+        let mid = self.first.next()? * self.mid;
+        let side = self.second.next()? * self.side;
+        self.right = Some(mid - side);
+        Some(mid + side)
+    }
+}
+
+impl<N> Source for StereoNoise<N>
+where
+    N: Iterator<Item = f32>,
+{
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
+
+// A noise color at the given gain, in mono or from a fresh generator for
+// each side in stereo
+pub fn source<N>(generator: fn() -> N, params: NoiseParams, gain: f32) -> Box<dyn Source<Item = f32> + Send>
+where
+    N: Source<Item = f32> + Send + 'static,
+{
+    if params.stereo {
+        Box::new(StereoNoise::new(generator(), generator(), params.width).amplify(gain))
+    } else {
+        Box::new(generator().amplify(gain))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stereo_width_sets_the_correlation_but_not_the_level() {
+        let frames = SAMPLE_RATE as usize * 10;
+        let mono: Vec<f32> = PinkNoise::with_seed(1).take(frames).collect();
+        let mono_power = mono.iter().map(|s| s * s).sum::<f32>() / frames as f32;
+
+        for width in [0.0, 0.5, 1.0] {
+            let samples: Vec<f32> = StereoNoise::new(PinkNoise::with_seed(1), PinkNoise::with_seed(2), width)
+                .take(2 * frames)
+                .collect();
+            let (left, right): (Vec<f32>, Vec<f32>) = samples.chunks(2).map(|frame| (frame[0], frame[1])).unzip();
+            let power = |side: &[f32]| side.iter().map(|s| s * s).sum::<f32>() / frames as f32;
+            let shared = left.iter().zip(&right).map(|(l, r)| l * r).sum::<f32>() / frames as f32;
+            let correlation = shared / (power(&left) * power(&right)).sqrt();
+
+            let expected = (width * std::f32::consts::FRAC_PI_2).cos();
+            assert!((correlation - expected).abs() < 0.05, "width {}: correlation {}", width, correlation);
+            for side in [&left, &right] {
+                assert!((power(side) / mono_power - 1.0).abs() < 0.1, "width {}: level changed", width);
+            }
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use crate::eq::Biquad;
+use crate::noise::{BrownNoise, PinkNoise, SAMPLE_RATE};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use rodio::Source;
+use serde::Deserialize;
+
+// Range of time between wave crests, in seconds
+const MIN_WAVE_PERIOD: f32 = 8.0;
+const MAX_WAVE_PERIOD: f32 = 15.0;
+// Share of a wave's period spent building up before it breaks
+const BUILD_UP: f32 = 0.35;
+// The wash filter is retuned this often, in frames, rather than every sample
+const FILTER_UPDATE_INTERVAL: u32 = 32;
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct OceanParams {
+    // From gentle lapping (0.0) to heavy surf (1.0)
+    pub wave_size: f32,
+    // 0.0 is mono, 1.0 has fully independent left and right surf
+    pub stereo_spread: f32,
+    // Fixed seed for a reproducible sea; random when unset
+    pub seed: Option<u64>,
+}
+
+impl Default for OceanParams {
+    fn default() -> Self {
+        OceanParams {
+            wave_size: 0.5,
+            stereo_spread: 0.6,
+            seed: None,
+        }
+    }
+}
+
+// One wave: how long it lasts, how big it is and where it breaks in the stereo field
+struct Wave {
+    length: u32,
+    position: u32,
+    height: f32,
+    // -1.0 is fully left, 1.0 fully right
+    pan: f32,
+}
+
+impl Wave {
+    // Rises slowly as the wave builds, then decays as the wash recedes
+    fn envelope(&self) -> f32 {
+        let phase = self.position as f32 / self.length as f32;
+        let shape = if phase < BUILD_UP {
+            let rise = phase / BUILD_UP;
+            rise * rise
+        } else {
+            (-(phase - BUILD_UP) * 5.0).exp()
+        };
+        shape * self.height
+    }
+}
+
+// Ocean surf built from the noise generators: a brown noise rumble for the
+// swell underneath and pink noise wash on top, both shaped by slow wave
+// envelopes with randomized 8-15 s periods. Breaking waves open up a low-pass
+// filter on the wash, so they sound brighter at the crest.
+//
+// Every random choice comes from one seeded RNG, so a given seed always
+// produces the same sea.
+pub struct Ocean {
+    rng: StdRng,
+    wave_size: f32,
+    stereo_spread: f32,
+    wave: Wave,
+    rumble: BrownNoise,
+    rumble_filter: Biquad,
+    // Wash noise shared by both channels, and independent per side
+    wash_center: PinkNoise,
+    wash_left: PinkNoise,
+    wash_right: PinkNoise,
+    wash_filters: [Biquad; 2],
+    frames_until_filter_update: u32,
+    // Right sample of the current frame, handed out on the next call
+    pending_right: Option<f32>,
+}
+
+impl Ocean {
+    pub fn new(params: OceanParams) -> Self {
+        let mut rng = match params.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let sample_rate = SAMPLE_RATE as f32;
+        let wave_size = params.wave_size.clamp(0.0, 1.0);
+
+        let mut ocean = Ocean {
+            rumble: BrownNoise::with_seed(rng.next_u64()),
+            wash_center: PinkNoise::with_seed(rng.next_u64()),
+            wash_left: PinkNoise::with_seed(rng.next_u64()),
+            wash_right: PinkNoise::with_seed(rng.next_u64()),
+            rumble_filter: Biquad::low_pass(300.0, sample_rate),
+            wash_filters: [
+                Biquad::low_pass(500.0, sample_rate),
+                Biquad::low_pass(500.0, sample_rate),
+            ],
+            frames_until_filter_update: 0,
+            wave: Wave {
+                length: 1,
+                position: 0,
+                height: 0.0,
+                pan: 0.0,
+            },
+            wave_size,
+            stereo_spread: params.stereo_spread.clamp(0.0, 1.0),
+            pending_right: None,
+            rng,
+        };
+        ocean.next_wave();
+        // Start part-way into the first wave rather than from silence
+        ocean.wave.position = (ocean.wave.length as f32 * BUILD_UP * 0.5) as u32;
+        ocean
+    }
+
+    fn next_wave(&mut self) {
+        let period = self.rng.gen_range(MIN_WAVE_PERIOD..MAX_WAVE_PERIOD);
+        let height = self.rng.gen_range(0.6..1.0);
+        let pan = self.rng.gen_range(-1.0..1.0) * self.stereo_spread;
+        self.wave = Wave {
+            length: (period * SAMPLE_RATE as f32) as u32,
+            position: 0,
+            height,
+            pan,
+        };
+    }
+
+    fn next_frame(&mut self) -> Option<(f32, f32)> {
+        if self.wave.position >= self.wave.length {
+            self.next_wave();
+        }
+        let envelope = self.wave.envelope();
+        self.wave.position += 1;
+
+        // Bigger, higher waves sound brighter when they break
+        if self.frames_until_filter_update == 0 {
+            let cutoff = 400.0 + envelope * (1500.0 + 4500.0 * self.wave_size);
+            let filter = Biquad::low_pass(cutoff, SAMPLE_RATE as f32);
+            for wash_filter in &mut self.wash_filters {
+                wash_filter.retune(&filter);
+            }
+            self.frames_until_filter_update = FILTER_UPDATE_INTERVAL;
+        }
+        self.frames_until_filter_update -= 1;
+
+        let rumble = self.rumble_filter.process(self.rumble.next()?) * (0.4 + 0.6 * self.wave_size);
+        let rumble = rumble * (0.5 + 0.5 * envelope);
+
+        // Mix shared and per-side noise by the spread, keeping the power constant
+        let center = self.wash_center.next()? * (1.0 - self.stereo_spread).sqrt();
+        let side = self.stereo_spread.sqrt();
+        let left = center + self.wash_left.next()? * side;
+        let right = center + self.wash_right.next()? * side;
+
+        // Constant-power placement of the breaking wave
+        let angle = (self.wave.pan + 1.0) * std::f32::consts::FRAC_PI_4;
+        let wash_level = envelope * (0.5 + 0.5 * self.wave_size) * std::f32::consts::SQRT_2;
+        let left = self.wash_filters[0].process(left) * wash_level * angle.cos();
+        let right = self.wash_filters[1].process(right) * wash_level * angle.sin();
+
+        Some((rumble + left, rumble + right))
+    }
+}
+
+impl Iterator for Ocean {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(right) = self.pending_right.take() {
+            return Some(right);
+        }
+        let (left, right) = self.next_frame()?;
+        self.pending_right = Some(right);
+        Some(left)
+    }
+}
+
+impl Source for Ocean {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(seed: u64) -> Vec<f32> {
+        let params = OceanParams {
+            seed: Some(seed),
+            ..OceanParams::default()
+        };
+        Ocean::new(params).take(2 * 48000).collect()
+    }
+
+    fn ocean(wave_size: f32, stereo_spread: f32) -> Ocean {
+        Ocean::new(OceanParams { wave_size, stereo_spread, seed: Some(3) })
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_sea() {
+        assert_eq!(render(7), render(7));
+        assert_ne!(render(7), render(8));
+    }
+
+    #[test]
+    fn swells_last_8_to_15_seconds() {
+        let mut ocean = ocean(0.5, 0.6);
+        let periods: Vec<f32> = (0..500)
+            .map(|_| {
+                ocean.next_wave();
+                ocean.wave.length as f32 / SAMPLE_RATE as f32
+            })
+            .collect();
+        assert!(periods.iter().all(|period| (MIN_WAVE_PERIOD..MAX_WAVE_PERIOD).contains(period)));
+        // Spread over the whole range rather than bunched up
+        assert!(periods.iter().any(|period| *period < 9.0) && periods.iter().any(|period| *period > 14.0));
+    }
+
+    #[test]
+    fn stereo_spread_sets_the_correlation() {
+        // Correlation of left and right over half a minute
+        let correlation = |stereo_spread: f32| {
+            let samples: Vec<f32> = ocean(0.5, stereo_spread).take(30 * 2 * SAMPLE_RATE as usize).collect();
+            let (mut lr, mut ll, mut rr) = (0.0, 0.0, 0.0);
+            for frame in samples.chunks(2) {
+                lr += frame[0] * frame[1];
+                ll += frame[0] * frame[0];
+                rr += frame[1] * frame[1];
+            }
+            lr / (ll * rr).sqrt()
+        };
+        let (mono, some, wide) = (correlation(0.0), correlation(0.5), correlation(1.0));
+        assert!(mono > 0.999 && mono > some && some > wide, "{} {} {}", mono, some, wide);
+    }
+
+    #[test]
+    fn wave_size_sets_the_level_and_brightness() {
+        // Same waves at the same times, only bigger
+        let measure = |wave_size: f32| {
+            let samples: Vec<f32> = ocean(wave_size, 0.0).step_by(2).take(30 * SAMPLE_RATE as usize).collect();
+            let power: f32 = samples.iter().map(|s| s * s).sum();
+            let change: f32 = samples.windows(2).map(|pair| (pair[1] - pair[0]).powi(2)).sum();
+            ((power / samples.len() as f32).sqrt(), change / power)
+        };
+        let (small, big) = (measure(0.0), measure(1.0));
+        assert!(big.0 > 1.5 * small.0 && big.1 > small.1, "{:?} {:?}", small, big);
+    }
+}
@@ -0,0 +1,130 @@
+use rodio::Source;
+use rodio::source::UniformSourceIterator;
+use serde::Deserialize;
+use std::f32::consts::{FRAC_PI_4, SQRT_2};
+use std::time::Duration;
+
+// Left and right gains for a position from -1.0 (hard left) to 1.0 (hard
+// right). Constant power: the total stays the same wherever the sound is,
+// scaled so the center leaves both sides at full level.
+pub fn gains(position: f32) -> [f32; 2] {
+    let angle = (1.0 + position.clamp(-1.0, 1.0)) * FRAC_PI_4;
+    [angle.cos() * SQRT_2, angle.sin() * SQRT_2]
+}
+
+// Where a sound sits in the stereo field, from a [position.<sound>] table in
+// the config. A pan LFO in [[modulation.<sound>]] sweeps it around this spot.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct Position {
+    // -1.0 is hard left, 1.0 hard right
+    pub pan: f32,
+}
+
+// Places any source in the stereo field. Mono sources are panned; stereo
+// ones are balanced, turning one side down as the other comes up.
+pub struct Panner<S>
+where
+    S: Source<Item = f32>,
+{
+    source: UniformSourceIterator<S>,
+    gains: [f32; 2],
+    channel: usize,
+}
+
+impl<S> Panner<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(source: S, position: Position) -> Self {
+        let sample_rate = source.sample_rate();
+        Panner {
+            source: UniformSourceIterator::new(source, 2, sample_rate),
+            gains: gains(position.pan),
+            channel: 0,
+        }
+    }
+}
+
+impl<S> Iterator for Panner<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let gain = self.gains[self.channel];
+        self.channel = 1 - self.channel;
+        Some(self.source.next()? * gain)
+    }
+}
+
+impl<S> Source for Panner<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::{PinkNoise, StereoNoise};
+
+    #[test]
+    fn panning_keeps_constant_power() {
+        assert_eq!(gains(0.0).map(|gain| (gain * 1000.0).round()), [1000.0, 1000.0]);
+        for step in -10..=10 {
+            let [left, right] = gains(step as f32 / 10.0);
+            assert!((left * left + right * right - 2.0).abs() < 1e-5);
+        }
+        let [left, right] = gains(-1.0);
+        assert!((left - SQRT_2).abs() < 1e-6 && right.abs() < 1e-6);
+    }
+
+    #[test]
+    fn places_a_mono_source() {
+        let hard_left = Panner::new(PinkNoise::with_seed(1), Position { pan: -1.0 });
+        assert_eq!(hard_left.channels(), 2);
+        let samples: Vec<f32> = hard_left.take(2000).collect();
+        assert!(samples.iter().skip(1).step_by(2).all(|sample| sample.abs() < 1e-6));
+        assert!(samples.iter().step_by(2).any(|sample| *sample != 0.0));
+
+        // Off to the right: the same noise in both channels, louder on the right
+        let [left, right] = gains(0.4);
+        let placed: Vec<f32> = Panner::new(PinkNoise::with_seed(1), Position { pan: 0.4 }).take(2000).collect();
+        for (frame, sample) in placed.chunks(2).zip(PinkNoise::with_seed(1)) {
+            assert!((frame[0] - sample * left).abs() < 1e-6 && (frame[1] - sample * right).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn balances_a_stereo_source() {
+        let stereo = || StereoNoise::new(PinkNoise::with_seed(1), PinkNoise::with_seed(2), 1.0);
+        let centered = Panner::new(stereo(), Position::default()).take(2000);
+        assert!(centered.zip(stereo()).all(|(centered, plain)| (centered - plain).abs() < 1e-6));
+
+        // Each side keeps its own signal, only turned up or down
+        let [left, right] = gains(-0.5);
+        let balanced: Vec<f32> = Panner::new(stereo(), Position { pan: -0.5 }).take(2000).collect();
+        let plain: Vec<f32> = stereo().take(2000).collect();
+        for (frame, plain) in balanced.chunks(2).zip(plain.chunks(2)) {
+            assert!((frame[0] - plain[0] * left).abs() < 1e-6 && (frame[1] - plain[1] * right).abs() < 1e-6);
+        }
+        assert!(left > 1.0 && right < 1.0);
+    }
+}
@@ -0,0 +1,190 @@
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+// Longest phase allowed, which keeps the time arithmetic in range
+const MAX_PHASE_MINUTES: f32 = 24.0 * 60.0;
+
+// Durations and sounds for Pomodoro mode, from the config's [pomodoro] table
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct PomodoroConfig {
+    pub work_minutes: f32,
+    pub short_break_minutes: f32,
+    pub long_break_minutes: f32,
+    // Every this many work sessions, the break is a long one
+    pub sessions_before_long_break: u32,
+    // A built-in sound for breaks, like "ocean"; breaks are silent when unset
+    pub break_sound: Option<String>,
+    // Chime at every change between work and break
+    pub chime: bool,
+}
+
+impl Default for PomodoroConfig {
+    fn default() -> Self {
+        PomodoroConfig {
+            work_minutes: 25.0,
+            short_break_minutes: 5.0,
+            long_break_minutes: 15.0,
+            sessions_before_long_break: 4,
+            break_sound: None,
+            chime: true,
+        }
+    }
+}
+
+impl PomodoroConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, minutes) in [
+            ("work_minutes", self.work_minutes),
+            ("short_break_minutes", self.short_break_minutes),
+            ("long_break_minutes", self.long_break_minutes),
+        ] {
+            if !(0.0..=MAX_PHASE_MINUTES).contains(&minutes) {
+                return Err(format!("Pomodoro {} should be 0 to {}, not {}", name, MAX_PHASE_MINUTES, minutes));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Phase {
+    Work,
+    ShortBreak,
+    LongBreak,
+}
+
+// The work/break cycle. Only keeps time: the caller passes in the current
+// time and reacts to phase changes, so this runs without any audio.
+pub struct Pomodoro {
+    config: PomodoroConfig,
+    phase: Phase,
+    // Work sessions started so far, including the current one
+    session: u32,
+    phase_started: Instant,
+}
+
+impl Pomodoro {
+    pub fn new(config: PomodoroConfig, now: Instant) -> Self {
+        Pomodoro {
+            config,
+            phase: Phase::Work,
+            session: 1,
+            phase_started: now,
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn config(&self) -> &PomodoroConfig {
+        &self.config
+    }
+
+    fn length(&self, phase: Phase) -> Duration {
+        let minutes = match phase {
+            Phase::Work => self.config.work_minutes,
+            Phase::ShortBreak => self.config.short_break_minutes,
+            Phase::LongBreak => self.config.long_break_minutes,
+        };
+        // A zero-length phase would never let the cycle catch up
+        Duration::from_secs_f32((minutes * 60.0).clamp(1.0, MAX_PHASE_MINUTES * 60.0))
+    }
+
+    fn sessions_per_cycle(&self) -> u32 {
+        self.config.sessions_before_long_break.max(1)
+    }
+
+    pub fn remaining(&self, now: Instant) -> Duration {
+        (self.phase_started + self.length(self.phase)).saturating_duration_since(now)
+    }
+
+    // Moves to the phase that should be running at `now` and returns it, or
+    // None if it's still the same one. Each phase starts exactly when the
+    // last one was due to end, so the cycle doesn't drift with how often
+    // this is called, and catches up after the computer has been asleep.
+    pub fn advance(&mut self, now: Instant) -> Option<Phase> {
+        let old_phase = self.phase;
+        let old_session = self.session;
+        while now >= self.phase_started + self.length(self.phase) {
+            self.phase_started += self.length(self.phase);
+            self.phase = match self.phase {
+                Phase::Work if self.session.is_multiple_of(self.sessions_per_cycle()) => Phase::LongBreak,
+                Phase::Work => Phase::ShortBreak,
+                Phase::ShortBreak | Phase::LongBreak => {
+                    self.session += 1;
+                    Phase::Work
+                }
+            };
+        }
+        (self.phase != old_phase || self.session != old_session).then_some(self.phase)
+    }
+
+    // Whole minutes left in the phase, rounded up, for the countdown
+    pub fn minutes_left(&self, now: Instant) -> u64 {
+        self.remaining(now).as_secs().div_ceil(60)
+    }
+
+    pub fn status(&self, now: Instant) -> String {
+        let phase = match self.phase {
+            Phase::Work => {
+                let session = (self.session - 1) % self.sessions_per_cycle() + 1;
+                format!("focus {} of {}", session, self.sessions_per_cycle())
+            }
+            Phase::ShortBreak => "short break".to_string(),
+            Phase::LongBreak => "long break".to_string(),
+        };
+        format!("Pomodoro: {}, {} min left", phase, self.minutes_left(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minutes(minutes: u64) -> Duration {
+        Duration::from_secs(minutes * 60)
+    }
+
+    #[test]
+    fn cycles_through_work_and_breaks() {
+        let start = Instant::now();
+        let mut pomodoro = Pomodoro::new(PomodoroConfig::default(), start);
+        assert_eq!(pomodoro.status(start), "Pomodoro: focus 1 of 4, 25 min left");
+        assert_eq!(pomodoro.advance(start + minutes(24)), None);
+        assert_eq!(pomodoro.status(start + minutes(24)), "Pomodoro: focus 1 of 4, 1 min left");
+
+        assert_eq!(pomodoro.advance(start + minutes(25)), Some(Phase::ShortBreak));
+        assert_eq!(pomodoro.advance(start + minutes(30)), Some(Phase::Work));
+        assert_eq!(pomodoro.status(start + minutes(30)), "Pomodoro: focus 2 of 4, 25 min left");
+
+        // Sessions 2 to 4, then the long break after the fourth
+        let long_break = start + minutes(4 * 25 + 3 * 5);
+        assert_eq!(pomodoro.advance(long_break - Duration::from_secs(1)), Some(Phase::Work));
+        assert_eq!(pomodoro.advance(long_break), Some(Phase::LongBreak));
+        assert_eq!(pomodoro.advance(long_break + minutes(15)), Some(Phase::Work));
+        assert_eq!(pomodoro.status(long_break + minutes(15)), "Pomodoro: focus 1 of 4, 25 min left");
+    }
+
+    #[test]
+    fn catches_up_without_drifting() {
+        let start = Instant::now();
+        let config = PomodoroConfig {
+            work_minutes: 10.0,
+            short_break_minutes: 2.0,
+            ..PomodoroConfig::default()
+        };
+        let mut pomodoro = Pomodoro::new(config, start);
+
+        // Checked late, the break still ends on schedule
+        assert_eq!(pomodoro.advance(start + minutes(11)), Some(Phase::ShortBreak));
+        assert_eq!(pomodoro.remaining(start + minutes(11)), minutes(1));
+
+        // Asleep through a whole work session and a break: into work
+        // session 3, which started at minute 24
+        assert_eq!(pomodoro.advance(start + minutes(25)), Some(Phase::Work));
+        assert_eq!(pomodoro.status(start + minutes(25)), "Pomodoro: focus 3 of 4, 9 min left");
+        assert_eq!(pomodoro.advance(start + minutes(33)), None);
+    }
+}
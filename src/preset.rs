@@ -0,0 +1,202 @@
+use crate::SoundType;
+use crate::config::expand_home;
+use crate::eq::EqSettings;
+use crate::settings::Favorite;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// Bumped whenever the file format changes, with a step in `migrate` to
+// bring older files up to date
+const FORMAT_VERSION: i64 = 1;
+// Sensible ranges for the tone settings in a shared file
+const FREQ_RANGE_HZ: std::ops::RangeInclusive<f32> = 10.0..=24000.0;
+const GAIN_RANGE_DB: std::ops::RangeInclusive<f32> = -30.0..=30.0;
+
+// A file of favorites for sharing between machines, documented in the
+// README under "Sharing Favorites". Deliberately separate from the settings
+// file, so that can change without breaking shared files.
+#[derive(Serialize, Deserialize)]
+struct PresetFile {
+    version: i64,
+    presets: Vec<Preset>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Preset {
+    name: String,
+    // A built-in sound by name, or "file:" and the path of an audio file
+    sound: String,
+    // Percent
+    volume: u8,
+    #[serde(default)]
+    eq: EqSettings,
+}
+
+pub fn export(favorites: &[Favorite]) -> Result<String, Box<dyn std::error::Error>> {
+    let file = PresetFile {
+        version: FORMAT_VERSION,
+        presets: favorites
+            .iter()
+            .map(|favorite| Preset {
+                name: favorite.name.clone(),
+                sound: favorite.sound.clone(),
+                volume: favorite.volume,
+                eq: favorite.eq.clone(),
+            })
+            .collect(),
+    };
+    Ok(toml::to_string_pretty(&file)?)
+}
+
+// Reads a preset file of any version up to the current one. Every preset is
+// checked before any is returned, so a bad file changes nothing.
+pub fn import(contents: &str) -> Result<Vec<Favorite>, String> {
+    let mut table: toml::Table = toml::from_str(contents).map_err(|e| format!("not a valid TOML file: {}", e))?;
+    migrate(&mut table)?;
+    let file: PresetFile = table.try_into().map_err(|e| format!("not a valid preset file: {}", e))?;
+
+    let mut favorites: Vec<Favorite> = Vec::new();
+    for preset in file.presets {
+        validate(&preset).map_err(|e| format!("preset \"{}\": {}", preset.name, e))?;
+        if favorites.iter().any(|favorite| favorite.name.eq_ignore_ascii_case(preset.name.trim())) {
+            return Err(format!("preset \"{}\" appears twice", preset.name));
+        }
+        favorites.push(Favorite {
+            name: preset.name.trim().to_string(),
+            sound: preset.sound,
+            volume: preset.volume,
+            eq: preset.eq,
+        });
+    }
+    Ok(favorites)
+}
+
+// Brings an older file up to the current version, one version at a time
+fn migrate(table: &mut toml::Table) -> Result<(), String> {
+    let mut version = match table.get("version") {
+        Some(version) => version.as_integer().ok_or("`version` should be a whole number")?,
+        None => 0,
+    };
+    if version > FORMAT_VERSION {
+        return Err(format!(
+            "made by a newer version of PlaySoundRust (format version {}, this one reads up to {})",
+            version, FORMAT_VERSION
+        ));
+    }
+
+    while version < FORMAT_VERSION {
+        match version {
+            // Version 0 has no `version` key: [[favorites]] tables copied out
+            // of settings.toml, which is how favorites were shared before
+            // this format. The fields are the same.
+            0 => {
+                let favorites = table
+                    .remove("favorites")
+                    .ok_or("not a preset file: it has neither `version` nor [[favorites]]")?;
+                table.insert("presets".to_string(), favorites);
+            }
+            _ => return Err(format!("unknown format version {}", version)),
+        }
+        version += 1;
+    }
+    table.insert("version".to_string(), toml::Value::Integer(version));
+    Ok(())
+}
+
+fn validate(preset: &Preset) -> Result<(), String> {
+    if preset.name.trim().is_empty() {
+        return Err("the name is empty".to_string());
+    }
+    if preset.volume > 100 {
+        return Err(format!("volume {} is over 100", preset.volume));
+    }
+    match preset.sound.strip_prefix("file:") {
+        Some(path) if !expand_home(Path::new(path)).is_file() => {
+            return Err(format!("the audio file {} isn't on this computer", path));
+        }
+        Some(_) => {}
+        None if SoundType::from_id(&preset.sound).is_none() => {
+            return Err(format!("unknown sound \"{}\"", preset.sound));
+        }
+        None => {}
+    }
+
+    let eq = &preset.eq;
+    let mut freqs: Vec<f32> = eq.high_pass_hz.into_iter().chain(eq.low_pass_hz).collect();
+    let mut gains = Vec::new();
+    for shelf in eq.low_shelf.iter().chain(&eq.high_shelf) {
+        freqs.push(shelf.freq_hz);
+        gains.push(shelf.gain_db);
+    }
+    for peak in &eq.peaks {
+        freqs.push(peak.freq_hz);
+        gains.push(peak.gain_db);
+        if !(peak.q > 0.0 && peak.q <= 20.0) {
+            return Err(format!("peak Q {} should be above 0 and at most 20", peak.q));
+        }
+    }
+    if let Some(freq) = freqs.iter().find(|freq| !FREQ_RANGE_HZ.contains(freq)) {
+        return Err(format!("EQ frequency {} Hz is out of range", freq));
+    }
+    if let Some(gain) = gains.iter().find(|gain| !GAIN_RANGE_DB.contains(gain)) {
+        return Err(format!("EQ gain {} dB is out of range", gain));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eq::TONE_PRESETS;
+
+    fn favorite(name: &str, sound: &str) -> Favorite {
+        Favorite {
+            name: name.to_string(),
+            sound: sound.to_string(),
+            volume: 40,
+            eq: TONE_PRESETS[2].1.clone(),
+        }
+    }
+
+    #[test]
+    fn exports_and_imports() {
+        let favorites = vec![favorite("Deep Focus", "brown_noise"), favorite("Evening", "rain")];
+        let exported = export(&favorites).unwrap();
+        assert!(exported.starts_with("version = 1\n"));
+        assert_eq!(import(&exported).unwrap(), favorites);
+    }
+
+    #[test]
+    fn migrates_favorites_copied_from_settings() {
+        let copied = r#"
+            [[favorites]]
+            name = "Deep Focus"
+            sound = "brown_noise"
+            volume = 40
+
+            [favorites.eq]
+            low_pass_hz = 6000.0
+        "#;
+        let imported = import(copied).unwrap();
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].eq.low_pass_hz, Some(6000.0));
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let with = |preset: &str| format!("version = 1\n[[presets]]\n{}", preset);
+        assert!(import(&with("name = \"A\"\nsound = \"pink_noise\"\nvolume = 50")).is_ok());
+        for bad in [
+            with("name = \" \"\nsound = \"pink_noise\"\nvolume = 50"),
+            with("name = \"A\"\nsound = \"static\"\nvolume = 50"),
+            with("name = \"A\"\nsound = \"file:/no/such/file.flac\"\nvolume = 50"),
+            with("name = \"A\"\nsound = \"pink_noise\"\nvolume = 150"),
+            with("name = \"A\"\nsound = \"pink_noise\"\nvolume = 50\neq = { high_pass_hz = 5.0 }"),
+            with("name = \"A\"\nsound = \"pink_noise\"\nvolume = 50\n[[presets]]\nname = \"a\"\nsound = \"rain\"\nvolume = 5"),
+            "version = 2\npresets = []".to_string(),
+            "volume = 50".to_string(),
+        ] {
+            assert!(import(&bad).is_err(), "accepted {}", bad);
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use crate::bell::{BellKind, Cue};
+use crate::noise::SAMPLE_RATE;
+use crate::sound_file::{FileSettings, SoundFile};
+use crate::volume::Volume;
+use crate::{Sound, SoundType};
+use rodio::Source;
+use rodio::source::UniformSourceIterator;
+use serde::Deserialize;
+use std::f32::consts::FRAC_PI_2;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+// Every step is converted to this format, so steps can be mixed sample by sample
+const CHANNELS: u16 = 2;
+
+// A timed sequence of sounds, one [[programs]] table each in the config
+#[derive(Clone, Deserialize)]
+pub struct ProgramConfig {
+    pub name: String,
+    pub steps: Vec<StepConfig>,
+    // Fade to silence over the end of the last step
+    #[serde(default)]
+    pub fade_out_seconds: f32,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct StepConfig {
+    // A built-in sound, by its settings key like "pink_noise"...
+    #[serde(default)]
+    pub sound: Option<String>,
+    // ...or an audio file
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    // Length of the step, including its fade in. Only the last step may leave
+    // it out, to play until stopped.
+    #[serde(default)]
+    pub minutes: Option<f32>,
+    // Level relative to the main volume, on the same curve
+    #[serde(default = "full_volume")]
+    pub volume: u8,
+    // Crossfade from the previous step, or fade in from silence for the first
+    #[serde(default)]
+    pub fade_seconds: f32,
+    // A bell, bowl or chime struck as the step starts, to mark the change
+    #[serde(default)]
+    pub cue: Option<BellKind>,
+}
+
+fn full_volume() -> u8 {
+    100
+}
+
+fn frames(seconds: f32) -> u64 {
+    (seconds.max(0.0) as f64 * SAMPLE_RATE as f64).round() as u64
+}
+
+impl ProgramConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.steps.is_empty() {
+            return Err(format!("program \"{}\" has no steps", self.name));
+        }
+        let open_ended = self.steps.iter().position(|step| step.minutes.is_none());
+        if open_ended.is_some_and(|index| index + 1 < self.steps.len()) {
+            return Err(format!("in program \"{}\", only the last step can leave out `minutes`", self.name));
+        }
+        for step in &self.steps {
+            if step.minutes.is_some_and(|minutes| !(minutes.is_finite() && minutes > 0.0)) {
+                return Err(format!("in program \"{}\", step `minutes` should be more than 0", self.name));
+            }
+            if !(step.fade_seconds.is_finite() && step.fade_seconds >= 0.0) {
+                return Err(format!("in program \"{}\", step `fade_seconds` should be 0 or more", self.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StepConfig {
+    pub fn sound(&self) -> Result<Sound, String> {
+        match (&self.sound, &self.file) {
+            (Some(id), None) => SoundType::from_id(id)
+                .map(Sound::Builtin)
+                .ok_or_else(|| format!("unknown sound \"{}\"", id)),
+            (None, Some(path)) => Ok(Sound::File(SoundFile {
+                path: path.clone(),
+                settings: FileSettings::default(),
+            })),
+            _ => Err("each step needs either `sound` or `file`".to_string()),
+        }
+    }
+
+    pub fn step(&self, source: Box<dyn Source<Item = f32> + Send>) -> Step {
+        Step {
+            source,
+            frames: self.minutes.map(|minutes| frames(minutes * 60.0)),
+            fade_frames: frames(self.fade_seconds),
+            gain: Volume::from_percent(self.volume.into()).amplitude(),
+            cue: self.cue.map(Cue::strike),
+        }
+    }
+}
+
+// A step ready to play
+pub struct Step {
+    pub source: Box<dyn Source<Item = f32> + Send>,
+    // Until stopped when unset
+    pub frames: Option<u64>,
+    pub fade_frames: u64,
+    pub gain: f32,
+    pub cue: Option<Cue>,
+}
+
+struct Playing {
+    source: UniformSourceIterator<Box<dyn Source<Item = f32> + Send>>,
+    frames: Option<u64>,
+    fade_frames: u64,
+    gain: f32,
+}
+
+impl Playing {
+    // Strikes the step's cue, if it has one
+    fn new(step: Step, cues: &mut Vec<Cue>) -> Self {
+        cues.extend(step.cue);
+        Playing {
+            source: UniformSourceIterator::new(step.source, CHANNELS, SAMPLE_RATE),
+            frames: step.frames,
+            fade_frames: step.fade_frames,
+            gain: step.gain,
+        }
+    }
+
+    // A source that runs dry just goes quiet
+    fn next_frame(&mut self) -> (f32, f32) {
+        let left = self.source.next().unwrap_or(0.0);
+        let right = self.source.next().unwrap_or(0.0);
+        (left * self.gain, right * self.gain)
+    }
+}
+
+// Plays the steps of a program back to back. Step lengths are counted in
+// frames of the output, so every change lands on an exact sample no matter
+// how the audio is buffered. During a step's fade the previous step keeps
+// playing underneath with equal-power crossfade curves. Ends after the last
+// step, or never if the last step is open-ended.
+pub struct Sequence {
+    upcoming: std::vec::IntoIter<Step>,
+    current: Option<Playing>,
+    outgoing: Option<Playing>,
+    // Frames played of the current step
+    frame: u64,
+    fade_out_frames: u64,
+    // Cues still ringing, over the steps
+    cues: Vec<Cue>,
+    // Index of the current step, for the tray; the step count once finished
+    progress: Arc<AtomicUsize>,
+    pending_right: Option<f32>,
+}
+
+impl Sequence {
+    pub fn new(steps: Vec<Step>, fade_out: Duration, progress: Arc<AtomicUsize>) -> Self {
+        let mut upcoming = steps.into_iter();
+        let mut cues = Vec::new();
+        progress.store(0, Ordering::Relaxed);
+        Sequence {
+            current: upcoming.next().map(|step| Playing::new(step, &mut cues)),
+            upcoming,
+            cues,
+            outgoing: None,
+            frame: 0,
+            fade_out_frames: frames(fade_out.as_secs_f32()),
+            progress,
+            pending_right: None,
+        }
+    }
+
+    fn next_frame(&mut self) -> Option<(f32, f32)> {
+        let current = self.current.as_ref()?;
+        if current.frames.is_some_and(|frames| self.frame >= frames) {
+            let next = self.upcoming.next().map(|step| Playing::new(step, &mut self.cues));
+            // Keep the old step around only if the new one fades in over it
+            let old = std::mem::replace(&mut self.current, next);
+            self.outgoing = old.filter(|_| self.current.as_ref().is_some_and(|next| next.fade_frames > 0));
+            self.frame = 0;
+            self.progress.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let current = self.current.as_mut()?;
+        let (mut left, mut right) = current.next_frame();
+
+        if self.frame < current.fade_frames {
+            let t = (self.frame as f32 + 0.5) / current.fade_frames as f32 * FRAC_PI_2;
+            left *= t.sin();
+            right *= t.sin();
+            if let Some(outgoing) = &mut self.outgoing {
+                let (out_left, out_right) = outgoing.next_frame();
+                left += out_left * t.cos();
+                right += out_right * t.cos();
+            }
+        } else {
+            self.outgoing = None;
+        }
+
+        // Fade the whole program out over the end of the last step
+        let remaining = current.frames.map(|frames| frames - self.frame);
+        // No fade-out leaves nothing to divide by
+        let last = self.upcoming.len() == 0 && self.fade_out_frames > 0;
+        if let Some(remaining) = remaining.filter(|remaining| last && *remaining <= self.fade_out_frames) {
+            let level = (remaining as f32 - 0.5) / self.fade_out_frames as f32 * FRAC_PI_2;
+            left *= level.sin();
+            right *= level.sin();
+        }
+
+        // Cues play at their own level, whatever the step's volume
+        let mut cue = 0.0;
+        self.cues.retain_mut(|ringing| match ringing.next() {
+            Some(sample) => {
+                cue += sample;
+                true
+            }
+            None => false,
+        });
+
+        self.frame += 1;
+        Some((left + cue, right + cue))
+    }
+}
+
+impl Iterator for Sequence {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(right) = self.pending_right.take() {
+            return Some(right);
+        }
+        let (left, right) = self.next_frame()?;
+        self.pending_right = Some(right);
+        Some(left)
+    }
+}
+
+impl Source for Sequence {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        CHANNELS
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A mono source that always outputs the same value
+    struct Constant(f32);
+
+    impl Iterator for Constant {
+        type Item = f32;
+
+        fn next(&mut self) -> Option<f32> {
+            Some(self.0)
+        }
+    }
+
+    impl Source for Constant {
+        fn current_span_len(&self) -> Option<usize> {
+            None
+        }
+
+        fn channels(&self) -> u16 {
+            1
+        }
+
+        fn sample_rate(&self) -> u32 {
+            SAMPLE_RATE
+        }
+
+        fn total_duration(&self) -> Option<Duration> {
+            None
+        }
+    }
+
+    fn step(value: f32, frames: u64, fade_frames: u64) -> Step {
+        Step {
+            source: Box::new(Constant(value)),
+            frames: Some(frames),
+            fade_frames,
+            gain: 1.0,
+            cue: None,
+        }
+    }
+
+    #[test]
+    fn steps_change_on_exact_frames() {
+        let progress = Arc::new(AtomicUsize::new(0));
+        let steps = vec![step(1.0, 100, 0), step(0.5, 50, 10)];
+        let output: Vec<f32> = Sequence::new(steps, Duration::ZERO, progress.clone()).collect();
+
+        assert_eq!(output.len(), 2 * 150);
+        assert!(output[..200].iter().all(|sample| *sample == 1.0));
+        // Equal-power crossfade: the outgoing step dominates at the start,
+        // the new one at the end
+        assert!(output[200] > 0.95);
+        assert!((output[218] - 0.577).abs() < 0.01);
+        assert!(output[220..].iter().all(|sample| *sample == 0.5));
+        assert_eq!(progress.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn only_the_last_step_may_be_open_ended() {
+        let config: ProgramConfig = toml::from_str(
+            r#"
+            name = "Wind Down"
+            steps = [
+                { sound = "pink_noise" },
+                { sound = "brown_noise", minutes = 30, volume = 30, cue = "bowl" },
+            ]
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_err());
+        assert!(config.steps[1].sound().is_ok());
+
+        let mut fixed = config.clone();
+        fixed.steps.swap(0, 1);
+        assert!(fixed.validate().is_ok());
+        for minutes in [0.0, -1.0, f32::NAN] {
+            fixed.steps[0].minutes = Some(minutes);
+            assert!(fixed.validate().is_err(), "accepted {} minutes", minutes);
+        }
+        fixed.steps[0].minutes = Some(30.0);
+        fixed.steps[1].fade_seconds = f32::INFINITY;
+        assert!(fixed.validate().is_err());
+        assert_eq!(config.steps[1].cue, Some(BellKind::Bowl));
+        assert!(StepConfig { sound: Some("static".to_string()), ..config.steps[0].clone() }.sound().is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use crate::eq::Biquad;
+use crate::noise::{PinkNoise, SAMPLE_RATE};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use rodio::Source;
+use serde::Deserialize;
+
+// Drops per second at density 1.0
+const MAX_DROP_RATE: f32 = 1500.0;
+// Upper bound on overlapping drops, which keeps the CPU cost fixed
+const MAX_ACTIVE_DROPS: usize = 64;
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct RainParams {
+    // How many drops fall, from a light sprinkle (0.0) to a downpour (1.0)
+    pub density: f32,
+    // How hard the rain sounds: louder bed and heavier drops
+    pub intensity: f32,
+    // Fixed seed for reproducible rain; random when unset
+    pub seed: Option<u64>,
+}
+
+impl Default for RainParams {
+    fn default() -> Self {
+        RainParams {
+            density: 0.5,
+            intensity: 0.5,
+            seed: None,
+        }
+    }
+}
+
+// A single drop: a decaying sine "plink" run as a rotating phasor
+struct Drop {
+    re: f32,
+    im: f32,
+    cos: f32,
+    sin: f32,
+    amplitude: f32,
+    decay: f32,
+}
+
+impl Drop {
+    fn next(&mut self) -> f32 {
+        let re = self.re * self.cos - self.im * self.sin;
+        self.im = self.re * self.sin + self.im * self.cos;
+        self.re = re;
+        self.amplitude *= self.decay;
+        self.im * self.amplitude
+    }
+}
+
+// Procedural rain: a band-limited pink noise bed whose level drifts slowly,
+// plus randomly timed drop transients. Nothing is sampled or looped, so it
+// never repeats.
+pub struct Rain {
+    rng: StdRng,
+    bed: PinkNoise,
+    bed_high_pass: Biquad,
+    bed_low_pass: Biquad,
+    bed_gain: f32,
+    // Slowly wandering bed level, so the rain swells and eases off
+    drift: f32,
+    drift_target: f32,
+    drops: Vec<Drop>,
+    drop_rate: f32,
+    drop_gain: f32,
+    samples_until_drop: f32,
+}
+
+impl Rain {
+    pub fn new(params: RainParams) -> Self {
+        let density = params.density.clamp(0.0, 1.0);
+        let intensity = params.intensity.clamp(0.0, 1.0);
+        let sample_rate = SAMPLE_RATE as f32;
+        let mut rng = match params.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        let mut rain = Rain {
+            bed: PinkNoise::with_seed(rng.next_u64()),
+            bed_high_pass: Biquad::high_pass(400.0, sample_rate),
+            bed_low_pass: Biquad::low_pass(6000.0 + 4000.0 * intensity, sample_rate),
+            bed_gain: 0.4 + 0.6 * intensity,
+            drift: 1.0,
+            drift_target: 1.0,
+            drops: Vec::with_capacity(MAX_ACTIVE_DROPS),
+            // Even the lightest setting has a few drops
+            drop_rate: 20.0 + MAX_DROP_RATE * density * density,
+            drop_gain: 0.15 + 0.35 * intensity,
+            samples_until_drop: 0.0,
+            rng,
+        };
+        rain.schedule_next_drop();
+        rain
+    }
+
+    // Drop arrivals are a Poisson process, so gaps are exponentially distributed
+    fn schedule_next_drop(&mut self) {
+        let u: f32 = self.rng.gen_range(f32::EPSILON..1.0);
+        self.samples_until_drop += -u.ln() / self.drop_rate * SAMPLE_RATE as f32;
+    }
+
+    fn spawn_drop(&mut self) {
+        if self.drops.len() >= MAX_ACTIVE_DROPS {
+            return;
+        }
+
+        // Small drops ring higher and shorter; most drops are small
+        let size: f32 = self.rng.gen_range(0.0f32..1.0).powi(3);
+        let freq = 5500.0 - 3500.0 * size + self.rng.gen_range(-400.0..400.0);
+        let decay_ms = 2.0 + 10.0 * size;
+        let w = 2.0 * std::f32::consts::PI * freq / SAMPLE_RATE as f32;
+
+        self.drops.push(Drop {
+            re: 1.0,
+            im: 0.0,
+            cos: w.cos(),
+            sin: w.sin(),
+            amplitude: self.drop_gain * (0.2 + 0.8 * size),
+            decay: (-1000.0 / (decay_ms * SAMPLE_RATE as f32)).exp(),
+        });
+    }
+}
+
+impl Iterator for Rain {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Pick a new bed level every so often and glide towards it over a few seconds
+        if self.rng.gen_range(0..SAMPLE_RATE) == 0 {
+            self.drift_target = self.rng.gen_range(0.6..1.0);
+        }
+        self.drift += (self.drift_target - self.drift) * 0.00002;
+
+        let pink = self.bed.next()?;
+        let bed = self.bed_low_pass.process(self.bed_high_pass.process(pink)) * self.bed_gain * self.drift;
+
+        self.samples_until_drop -= 1.0;
+        while self.samples_until_drop <= 0.0 {
+            self.spawn_drop();
+            self.schedule_next_drop();
+        }
+
+        let drops: f32 = self.drops.iter_mut().map(Drop::next).sum();
+        // Forget drops once they've decayed to inaudible (-80 dB)
+        self.drops.retain(|drop| drop.amplitude > 1e-4);
+
+        Some(bed + drops)
+    }
+}
+
+impl Source for Rain {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intensity_makes_it_louder() {
+        // Same drops at the same times, only harder
+        let rms = |intensity: f32| {
+            let rain = Rain::new(RainParams { intensity, seed: Some(5), ..RainParams::default() });
+            let samples: Vec<f32> = rain.take(10 * SAMPLE_RATE as usize).collect();
+            (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+        };
+        let (soft, medium, hard) = (rms(0.0), rms(0.5), rms(1.0));
+        assert!(soft < medium && medium < hard && hard > 2.0 * soft, "{} {} {}", soft, medium, hard);
+    }
+
+    #[test]
+    fn density_sets_the_drop_rate() {
+        for density in [0.0, 0.2, 1.0] {
+            let mut rain = Rain::new(RainParams { density, seed: Some(3), ..RainParams::default() });
+            // Count the drops scheduled in a minute
+            let end = 60.0 * SAMPLE_RATE as f32;
+            rain.samples_until_drop = 0.0;
+            let mut drops = 0;
+            while rain.samples_until_drop < end {
+                rain.schedule_next_drop();
+                drops += 1;
+            }
+            let expected = 60.0 * (20.0 + MAX_DROP_RATE * density * density);
+            assert!((drops as f32 / expected - 1.0).abs() < 0.1, "density {}: {} drops", density, drops);
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use crate::volume::Volume;
+use crate::{Sound, SoundType};
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime, Weekday};
+use serde::Deserialize;
+
+// One [[schedule]] table in the config: play from `start` to `stop` on the
+// given days
+#[derive(Clone, Deserialize)]
+pub struct ScheduleConfig {
+    // Day names like "mon" or "friday", or "weekdays", "weekends" or
+    // "daily"; every day when empty
+    #[serde(default)]
+    pub days: Vec<String>,
+    // Local times like "09:00". A stop before the start runs past midnight.
+    pub start: String,
+    pub stop: String,
+    // A built-in sound by name; the selected sound when unset
+    #[serde(default)]
+    pub sound: Option<String>,
+    // Percent; the volume is left alone when unset
+    #[serde(default)]
+    pub volume: Option<u8>,
+}
+
+// A parsed schedule entry
+pub struct Slot {
+    // Indexed by days from Monday
+    days: [bool; 7],
+    start: NaiveTime,
+    stop: NaiveTime,
+    pub sound: Option<Sound>,
+    pub volume: Option<Volume>,
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| format!("schedule time \"{}\" should look like \"09:00\"", time))
+}
+
+impl ScheduleConfig {
+    pub fn slot(&self) -> Result<Slot, String> {
+        let mut days = [self.days.is_empty(); 7];
+        for day in &self.days {
+            match day.to_lowercase().as_str() {
+                "daily" => days = [true; 7],
+                "weekdays" => days[..5].fill(true),
+                "weekends" => days[5..].fill(true),
+                name => {
+                    let weekday: Weekday = name.parse().map_err(|_| format!("unknown day \"{}\" in schedule", day))?;
+                    days[weekday.num_days_from_monday() as usize] = true;
+                }
+            }
+        }
+        let sound = match &self.sound {
+            Some(id) => Some(Sound::Builtin(
+                SoundType::from_id(id).ok_or_else(|| format!("unknown sound \"{}\" in schedule", id))?,
+            )),
+            None => None,
+        };
+        Ok(Slot {
+            days,
+            start: parse_time(&self.start)?,
+            stop: parse_time(&self.stop)?,
+            sound,
+            volume: self.volume.map(|percent| Volume::from_percent(percent.into())),
+        })
+    }
+}
+
+impl Slot {
+    fn on(&self, day: Weekday) -> bool {
+        self.days[day.num_days_from_monday() as usize]
+    }
+
+    fn contains(&self, now: NaiveDateTime) -> bool {
+        let time = now.time();
+        let today = now.weekday();
+        if self.start < self.stop {
+            self.on(today) && time >= self.start && time < self.stop
+        } else {
+            // Overnight: belongs to the day it starts on
+            (self.on(today) && time >= self.start) || (self.on(today.pred()) && time < self.stop)
+        }
+    }
+}
+
+// Where the scheduler gets the time from, so tests can set it
+pub trait Clock {
+    fn now(&self) -> NaiveDateTime;
+}
+
+pub struct LocalClock;
+
+impl Clock for LocalClock {
+    fn now(&self) -> NaiveDateTime {
+        Local::now().naive_local()
+    }
+}
+
+pub enum Change<'a> {
+    Start(&'a Slot),
+    Stop,
+}
+
+// Works out from the clock which slot should be playing, and reports when
+// that changes. Nothing is counted down, so jumps of the clock in either
+// direction, daylight saving and sleep just move it to the right slot on the
+// next poll. Playback changed by hand in the middle of a slot is left alone
+// until the next change.
+pub struct Scheduler<C: Clock = LocalClock> {
+    slots: Vec<Slot>,
+    clock: C,
+    // Index of the slot playing at the last poll
+    active: Option<usize>,
+}
+
+impl<C: Clock> Scheduler<C> {
+    pub fn new(slots: Vec<Slot>, clock: C) -> Self {
+        Scheduler {
+            slots,
+            clock,
+            active: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn poll(&mut self) -> Option<Change<'_>> {
+        let now = self.clock.now();
+        // The first matching entry wins where they overlap
+        let active = self.slots.iter().position(|slot| slot.contains(now));
+        if active == self.active {
+            return None;
+        }
+        self.active = active;
+        match active {
+            Some(index) => Some(Change::Start(&self.slots[index])),
+            None => Some(Change::Stop),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    struct FixedClock(NaiveDateTime);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> NaiveDateTime {
+            self.0
+        }
+    }
+
+    // 2026-03-02 is a Monday
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 3, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn entry(days: &[&str], start: &str, stop: &str) -> Slot {
+        ScheduleConfig {
+            days: days.iter().map(|day| day.to_string()).collect(),
+            start: start.to_string(),
+            stop: stop.to_string(),
+            sound: Some("brown_noise".to_string()),
+            volume: Some(40),
+        }
+        .slot()
+        .unwrap()
+    }
+
+    // What `poll` reported at each time: the slot's start time, "stop" or nothing
+    fn run(scheduler: &mut Scheduler<FixedClock>, times: &[NaiveDateTime]) -> Vec<Option<String>> {
+        times
+            .iter()
+            .map(|time| {
+                scheduler.clock = FixedClock(*time);
+                scheduler.poll().map(|change| match change {
+                    Change::Start(slot) => slot.start.format("%H:%M").to_string(),
+                    Change::Stop => "stop".to_string(),
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn starts_and_stops_on_schedule() {
+        let slots = vec![entry(&["weekdays"], "09:00", "12:00"), entry(&["sat"], "22:00", "06:30")];
+        let mut scheduler = Scheduler::new(slots, FixedClock(at(2, 8, 0)));
+        let changes = run(
+            &mut scheduler,
+            &[at(2, 8, 59), at(2, 9, 0), at(2, 11, 0), at(2, 12, 0), at(7, 9, 0), at(7, 22, 0), at(8, 6, 0), at(8, 6, 30)],
+        );
+        let expected = [None, Some("09:00"), None, Some("stop"), None, Some("22:00"), None, Some("stop")];
+        assert_eq!(changes, expected.map(|change| change.map(str::to_string)));
+    }
+
+    #[test]
+    fn follows_the_clock_when_it_jumps() {
+        let slots = vec![entry(&[], "09:00", "12:00")];
+        let mut scheduler = Scheduler::new(slots, FixedClock(at(2, 8, 0)));
+        // Forward into the slot, back out of it, then a day ahead into it
+        let changes = run(&mut scheduler, &[at(2, 10, 0), at(2, 7, 0), at(3, 10, 0), at(3, 10, 1)]);
+        let expected = [Some("09:00"), Some("stop"), Some("09:00"), None];
+        assert_eq!(changes, expected.map(|change| change.map(str::to_string)));
+    }
+
+    #[test]
+    fn rejects_bad_entries() {
+        let config = ScheduleConfig {
+            days: vec!["someday".to_string()],
+            start: "09:00".to_string(),
+            stop: "12:00".to_string(),
+            sound: None,
+            volume: None,
+        };
+        assert!(config.slot().is_err());
+        assert!(ScheduleConfig { days: Vec::new(), start: "9am".to_string(), ..config.clone() }.slot().is_err());
+        assert!(ScheduleConfig { days: Vec::new(), sound: Some("static".to_string()), ..config }.slot().is_err());
+    }
+}
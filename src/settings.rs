@@ -0,0 +1,202 @@
+use crate::config::config_dir;
+use crate::eq::EqSettings;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const SETTINGS_FILE_NAME: &str = "settings.toml";
+
+// Choices made in the app that should survive a restart. Unlike `Config`,
+// this file is written by the app, next to config.toml.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // Tone shaping per sound, keyed by `SoundType::id`
+    pub eq: BTreeMap<String, EqSettings>,
+    // Saved combinations, in the order they appear in the Favorites submenu
+    pub favorites: Vec<Favorite>,
+    // When the file was last read or written here, to notice another
+    // process writing it
+    #[serde(skip)]
+    modified: Option<SystemTime>,
+    // Set while the file can't be read, so saving doesn't replace what the
+    // user is in the middle of editing
+    #[serde(skip)]
+    unreadable: bool,
+}
+
+// A sound with its volume and tone, recalled in one click
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Favorite {
+    pub name: String,
+    // `Sound::id` of the sound
+    pub sound: String,
+    // Percent
+    pub volume: u8,
+    #[serde(default)]
+    pub eq: EqSettings,
+}
+
+fn settings_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(SETTINGS_FILE_NAME))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+impl Settings {
+    // Loads saved settings, or the defaults if there are none yet
+    pub fn load() -> Result<Self, String> {
+        let Some(path) = settings_path() else {
+            return Ok(Settings::default());
+        };
+
+        if !path.exists() {
+            return Ok(Settings::default());
+        }
+
+        let modified = modified(&path);
+        let settings: Settings = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| toml::from_str(&contents).map_err(|e| e.to_string()))
+            .map_err(|e| format!("Error reading settings {}: {}", path.display(), e))?;
+        Ok(Settings { modified, ..settings })
+    }
+
+    // For the app, which keeps going with the defaults if the file can't be
+    // read, but leaves the file alone until it can
+    pub fn load_or_default() -> Self {
+        Settings::load().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            Settings {
+                modified: settings_path().and_then(|path| modified(&path)),
+                unreadable: true,
+                ..Settings::default()
+            }
+        })
+    }
+
+    pub fn save(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let path = settings_path().ok_or("no config directory on this system")?;
+        if self.unreadable {
+            return Err(format!("{} can't be read, so it wasn't saved over", path.display()).into());
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // Written next to it first, so another process never reads half a file
+        let temp = path.with_extension("toml.tmp");
+        std::fs::write(&temp, toml::to_string_pretty(self)?)?;
+        std::fs::rename(&temp, &path)?;
+        self.modified = modified(&path);
+        Ok(())
+    }
+
+    // Reloads if the file changed since it was last read or written here,
+    // e.g. by --import-favorites. Returns whether it did. A file that can't
+    // be read leaves the current settings in place.
+    pub fn poll(&mut self) -> bool {
+        let Some(path) = settings_path() else {
+            return false;
+        };
+        let modified = modified(&path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        match Settings::load() {
+            Ok(settings) => {
+                *self = settings;
+                println!("Settings changed, found {} favorites", self.favorites.len());
+                true
+            }
+            Err(e) => {
+                eprintln!("{}; keeping the current settings", e);
+                self.unreadable = true;
+                false
+            }
+        }
+    }
+
+    pub fn eq_for(&self, sound_id: &str) -> EqSettings {
+        self.eq.get(sound_id).cloned().unwrap_or_default()
+    }
+
+    pub fn favorite(&self, name: &str) -> Option<usize> {
+        self.favorites.iter().position(|favorite| favorite.name.eq_ignore_ascii_case(name))
+    }
+
+    // Replaces a favorite with the same name, otherwise adds it at the end
+    pub fn save_favorite(&mut self, favorite: Favorite) {
+        match self.favorite(&favorite.name) {
+            Some(index) => self.favorites[index] = favorite,
+            None => self.favorites.push(favorite),
+        }
+    }
+
+    pub fn rename_favorite(&mut self, name: &str, new_name: &str) -> Result<(), String> {
+        let index = self.favorite(name).ok_or_else(|| format!("no favorite named \"{}\"", name))?;
+        let new_name = new_name.trim();
+        if new_name.is_empty() {
+            return Err("a favorite needs a name".to_string());
+        }
+        if self.favorite(new_name).is_some_and(|other| other != index) {
+            return Err(format!("there is already a favorite named \"{}\"", new_name));
+        }
+        self.favorites[index].name = new_name.to_string();
+        Ok(())
+    }
+
+    // Flat settings are removed rather than stored
+    pub fn set_eq(&mut self, sound_id: &str, eq: EqSettings) {
+        if eq.is_flat() {
+            self.eq.remove(sound_id);
+        } else {
+            self.eq.insert(sound_id.to_string(), eq);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eq::TONE_PRESETS;
+
+    #[test]
+    fn favorites_survive_a_round_trip() {
+        let mut settings = Settings::default();
+        settings.set_eq("pink_noise", TONE_PRESETS[2].1.clone());
+        let favorite = Favorite {
+            name: "Deep Focus".to_string(),
+            sound: "brown_noise".to_string(),
+            volume: 40,
+            eq: TONE_PRESETS[1].1.clone(),
+        };
+        settings.save_favorite(favorite.clone());
+        settings.save_favorite(Favorite {
+            volume: 45,
+            ..favorite.clone()
+        });
+        assert_eq!(settings.favorites.len(), 1);
+
+        let saved: Settings = toml::from_str(&toml::to_string_pretty(&settings).unwrap()).unwrap();
+        assert_eq!(saved.eq, settings.eq);
+        assert_eq!(saved.favorites, settings.favorites);
+        assert_eq!(saved.favorite("deep focus"), Some(0));
+
+        settings.save_favorite(Favorite {
+            name: "Brown noise, Warm, 40%".to_string(),
+            ..favorite
+        });
+        assert!(settings.rename_favorite("brown noise, warm, 40%", "Deep Focus").is_err());
+        assert!(settings.rename_favorite("Brown noise, Warm, 40%", " ").is_err());
+        assert!(settings.rename_favorite("Rain", "Evening").is_err());
+        settings.rename_favorite("Brown noise, Warm, 40%", " Evening ").unwrap();
+        assert_eq!(settings.favorite("Evening"), Some(1));
+        // Only the case of its own name can change
+        settings.rename_favorite("evening", "EVENING").unwrap();
+        assert_eq!(settings.favorites[1].name, "EVENING");
+    }
+}
@@ -0,0 +1,397 @@
+use crate::config::expand_home;
+use rodio::{Decoder, Source};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::f32::consts::FRAC_PI_2;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// How to play an audio file. Set in the config's [[files]] tables, or in a
+// sidecar file next to a file in the sound library.
+#[derive(Clone, PartialEq, Default, Deserialize)]
+#[serde(default)]
+pub struct FileSettings {
+    // Menu label; the file name when unset
+    pub name: Option<String>,
+    // Level adjustment, since files aren't loudness-calibrated
+    pub gain_db: f32,
+    // Length of the crossfade where the end of the loop runs back into its
+    // start. 0 gives a plain gapless loop.
+    pub crossfade_seconds: f32,
+    // The loop region. Anything before the start plays once as an intro;
+    // anything after the end is never played.
+    pub loop_start_seconds: f32,
+    pub loop_end_seconds: Option<f32>,
+}
+
+impl FileSettings {
+    pub fn loop_points(&self) -> Result<LoopPoints, String> {
+        let seconds = |name: &str, seconds: f32| {
+            Duration::try_from_secs_f32(seconds.max(0.0)).map_err(|_| format!("{} = {} is out of range", name, seconds))
+        };
+        Ok(LoopPoints {
+            start: seconds("loop_start_seconds", self.loop_start_seconds)?,
+            end: self.loop_end_seconds.map(|end| seconds("loop_end_seconds", end)).transpose()?,
+            crossfade: seconds("crossfade_seconds", self.crossfade_seconds)?,
+        })
+    }
+}
+
+// An audio file offered next to the built-in sounds
+#[derive(Clone, PartialEq, Deserialize)]
+pub struct SoundFile {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub settings: FileSettings,
+}
+
+impl SoundFile {
+    // Stable identifier used as a key in the settings file
+    pub fn id(&self) -> String {
+        format!("file:{}", self.path.display())
+    }
+
+    pub fn label(&self) -> String {
+        match &self.settings.name {
+            Some(name) => name.clone(),
+            None => self
+                .path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| self.path.display().to_string()),
+        }
+    }
+
+    pub fn resolved_path(&self) -> PathBuf {
+        expand_home(&self.path)
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct LoopPoints {
+    pub start: Duration,
+    // The end of the file when unset
+    pub end: Option<Duration>,
+    pub crossfade: Duration,
+}
+
+// Set by a `LoopedFile` when it has to stop early, for the tray to report
+pub type PlaybackError = Arc<Mutex<Option<String>>>;
+
+type FileDecoder = Decoder<BufReader<File>>;
+
+fn open_decoder(path: &Path) -> Result<FileDecoder, Box<dyn std::error::Error>> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut builder = Decoder::builder()
+        .with_data(BufReader::new(file))
+        .with_byte_len(len)
+        .with_seekable(true);
+    if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+        builder = builder.with_hint(extension);
+    }
+    Ok(builder.build()?)
+}
+
+// Opens the file for another pass, decoded up to `skip` samples in
+fn reopen(path: &Path, channels: u16, sample_rate: u32, skip: usize) -> Result<FileDecoder, String> {
+    let mut decoder = open_decoder(path).map_err(|e| e.to_string())?;
+    if decoder.channels() != channels || decoder.sample_rate() != sample_rate {
+        return Err("the file's format changed while playing".to_string());
+    }
+    if decoder.by_ref().take(skip).count() < skip {
+        return Err("the file got shorter while playing".to_string());
+    }
+    Ok(decoder)
+}
+
+// Plays an audio file with its loop region repeating forever. The decoder
+// runs ahead of the output, so when it reaches the end of the loop the last
+// stretch is still queued and can be crossfaded with the loop's first
+// samples (`head`). Playback then carries on with a decoder that has been
+// through the file from the top to just past the part already played in the
+// crossfade. That decoder is prepared on another thread during the previous
+// pass, since skipping a long intro takes far longer than one audio
+// callback. Only about two crossfades' worth of audio is ever held in memory.
+pub struct LoopedFile {
+    path: PathBuf,
+    decoder: FileDecoder,
+    // The decoder for the next pass, being prepared
+    next_pass: Option<Receiver<Result<FileDecoder, String>>>,
+    channels: u16,
+    sample_rate: u32,
+    // Loop region in samples from the start of the file
+    loop_start: usize,
+    loop_end: usize,
+    // Samples read so far by the current decoder
+    position: usize,
+    // The first samples of the loop, faded in over the end of each pass
+    head: Vec<f32>,
+    queue: VecDeque<f32>,
+    error: PlaybackError,
+    // Set once looping has failed; only the queue is left to play
+    stopped: bool,
+}
+
+impl LoopedFile {
+    pub fn open(path: &Path, points: LoopPoints, error: PlaybackError) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut decoder = open_decoder(path)?;
+        let channels = decoder.channels();
+        let sample_rate = decoder.sample_rate();
+        let to_samples = |time: Duration| (time.as_secs_f64() * sample_rate as f64).round() as usize * channels as usize;
+
+        let loop_start = to_samples(points.start);
+        let loop_end = points.end.map(to_samples).unwrap_or(usize::MAX);
+        if loop_end <= loop_start {
+            return Err("the loop has to end after it starts".into());
+        }
+
+        // Read the head plus as much again, so the crossfade can't be longer
+        // than half the loop
+        let crossfade = to_samples(points.crossfade);
+        let wanted = (2 * crossfade).min(loop_end - loop_start).max(1);
+        let mut head_decoder = open_decoder(path)?;
+        head_decoder.by_ref().take(loop_start).for_each(drop);
+        let mut head: Vec<f32> = head_decoder.take(wanted).collect();
+        if head.is_empty() {
+            let problem = if loop_start == 0 { "contains no audio" } else { "is shorter than the loop start" };
+            return Err(format!("the file {}", problem).into());
+        }
+        head.truncate(crossfade.min(head.len() / 2 / channels as usize * channels as usize));
+
+        let queue: VecDeque<f32> = decoder.by_ref().take(head.len().max(1)).collect();
+        let mut looped = LoopedFile {
+            path: path.to_path_buf(),
+            decoder,
+            next_pass: None,
+            channels,
+            sample_rate,
+            loop_start,
+            loop_end,
+            position: queue.len(),
+            head,
+            queue,
+            error,
+            stopped: false,
+        };
+        looped.prepare_next_pass();
+        Ok(looped)
+    }
+
+    fn prepare_next_pass(&mut self) {
+        let (sender, receiver) = mpsc::channel();
+        let path = self.path.clone();
+        let (channels, sample_rate) = (self.channels, self.sample_rate);
+        let skip = self.loop_start + self.head.len();
+        std::thread::spawn(move || sender.send(reopen(&path, channels, sample_rate, skip)));
+        self.next_pass = Some(receiver);
+    }
+
+    // Crossfades the queued end of the loop into the head and starts the
+    // next pass
+    fn wrap_around(&mut self) -> Result<(), String> {
+        // Only waits if the loop is shorter than the time it takes to prepare
+        let decoder = match self.next_pass.take().map(|receiver| receiver.recv()) {
+            Some(Ok(decoder)) => decoder?,
+            _ => return Err("couldn't reopen the file".to_string()),
+        };
+        self.prepare_next_pass();
+
+        // Equal-power curves, since the two ends aren't correlated
+        let start = self.queue.len() - self.head.len();
+        let frames = self.head.len() / self.channels as usize;
+        for (i, head) in self.head.iter().enumerate() {
+            let t = ((i / self.channels as usize) as f32 + 0.5) / frames as f32;
+            let tail = &mut self.queue[start + i];
+            *tail = *tail * (t * FRAC_PI_2).cos() + head * (t * FRAC_PI_2).sin();
+        }
+
+        self.decoder = decoder;
+        self.position = self.loop_start + self.head.len();
+        Ok(())
+    }
+
+    fn read(&mut self) -> Option<f32> {
+        if self.position >= self.loop_end {
+            return None;
+        }
+        self.position += 1;
+        self.decoder.next()
+    }
+}
+
+impl Iterator for LoopedFile {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.stopped {
+            return self.queue.pop_front();
+        }
+        let sample = match self.read() {
+            Some(sample) => Ok(sample),
+            // The next pass can still come up empty if the file was replaced
+            None => self
+                .wrap_around()
+                .and_then(|()| self.read().ok_or_else(|| "the file got shorter while playing".to_string())),
+        };
+        match sample {
+            Ok(sample) => self.queue.push_back(sample),
+            Err(e) => {
+                *self.error.lock().unwrap() = Some(format!("Stopped looping {}: {}", self.path.display(), e));
+                // Play out what's queued, then end
+                self.stopped = true;
+            }
+        }
+        self.queue.pop_front()
+    }
+}
+
+impl Source for LoopedFile {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes a mono 16-bit WAV file of the given samples
+    fn write_wav(name: &str, samples: &[i16]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("playsoundrust-{}-{}.wav", name, std::process::id()));
+        let data_len = (samples.len() * 2) as u32;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&48000u32.to_le_bytes());
+        bytes.extend_from_slice(&96000u32.to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        for sample in samples {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn no_error() -> PlaybackError {
+        Arc::new(Mutex::new(None))
+    }
+
+    fn crossfade(crossfade: Duration) -> LoopPoints {
+        LoopPoints {
+            crossfade,
+            ..LoopPoints::default()
+        }
+    }
+
+    #[test]
+    fn loops_without_a_gap() {
+        let samples: Vec<i16> = (1..=480).map(|i| i * 64).collect();
+        let path = write_wav("gapless", &samples);
+        let looped = LoopedFile::open(&path, LoopPoints::default(), no_error()).unwrap();
+        let output: Vec<f32> = looped.take(samples.len() * 3).collect();
+        std::fs::remove_file(&path).ok();
+
+        let pass = &output[..samples.len()];
+        assert!(pass.iter().all(|sample| *sample != 0.0));
+        assert_eq!(pass, &output[samples.len()..2 * samples.len()]);
+        assert_eq!(pass, &output[2 * samples.len()..]);
+    }
+
+    #[test]
+    fn crossfades_the_end_into_the_start() {
+        // One second of a constant level: an equal-power crossfade of two
+        // identical halves dips by no more than 3 dB
+        let samples = vec![16384i16; 48000];
+        let path = write_wav("crossfade", &samples);
+        let looped = LoopedFile::open(&path, crossfade(Duration::from_millis(100)), no_error()).unwrap();
+        let output: Vec<f32> = looped.take(48000 * 3).collect();
+        std::fs::remove_file(&path).ok();
+
+        let level = 0.5;
+        assert!(output.iter().all(|sample| *sample >= level * 0.99 && *sample <= level * 1.42));
+        // Each pass is one second minus the overlap, and the first crossfade
+        // sits right at the end of the first pass
+        let seam = 48000 - 4800;
+        assert!((output[seam + 2400] - level * std::f32::consts::SQRT_2).abs() < 0.01);
+        assert!((output[seam - 1] - level).abs() < 1e-3);
+    }
+
+    #[test]
+    fn plays_the_intro_once_then_repeats_the_loop() {
+        // 20 ms ramp, looping from 5 ms to 15 ms
+        let samples: Vec<i16> = (1..=960).map(|i| i * 32).collect();
+        let path = write_wav("loop-points", &samples);
+        let points = LoopPoints {
+            start: Duration::from_millis(5),
+            end: Some(Duration::from_millis(15)),
+            crossfade: Duration::ZERO,
+        };
+        let looped = LoopedFile::open(&path, points, no_error()).unwrap();
+        let output: Vec<f32> = looped.take(720 + 480 * 2).collect();
+        std::fs::remove_file(&path).ok();
+
+        let expected: Vec<f32> = samples[..720]
+            .iter()
+            .chain(&samples[240..720])
+            .chain(&samples[240..720])
+            .map(|sample| *sample as f32 / 32768.0)
+            .collect();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn reports_a_file_that_runs_out_and_plays_out_the_queue() {
+        let samples = vec![16384i16; 480];
+        let path = write_wav("runs-out", &samples);
+        let error = no_error();
+        let mut looped = LoopedFile::open(&path, crossfade(Duration::from_millis(1)), error.clone()).unwrap();
+        // The file is replaced by one that ends right where the next pass
+        // should carry on
+        let skip = looped.head.len();
+        let shorter = write_wav("runs-out-shorter", &samples[..skip]);
+        let (sender, receiver) = mpsc::channel();
+        sender.send(reopen(&shorter, 1, 48000, skip)).unwrap();
+        looped.next_pass = Some(receiver);
+        let output: Vec<f32> = looped.take(samples.len() * 2).collect();
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_file(&shorter).ok();
+
+        // The whole pass is heard, including the crossfaded tail
+        assert_eq!(output.len(), samples.len());
+        assert!(error.lock().unwrap().as_ref().is_some_and(|e| e.contains("got shorter")));
+    }
+
+    #[test]
+    fn reports_files_it_cannot_decode() {
+        let path = std::env::temp_dir().join(format!("playsoundrust-garbage-{}.wav", std::process::id()));
+        std::fs::write(&path, b"this is not audio").unwrap();
+        let result = LoopedFile::open(&path, LoopPoints::default(), no_error());
+        std::fs::remove_file(&path).ok();
+        assert!(result.is_err());
+        assert!(LoopedFile::open(Path::new("/no/such/file.wav"), LoopPoints::default(), no_error()).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+use crate::eq::Biquad;
+use crate::noise::{BrownNoise, SAMPLE_RATE};
+use crate::rain::{Rain, RainParams};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use rodio::Source;
+use serde::Deserialize;
+
+// Upper bound on overlapping thunder rolls
+const MAX_ACTIVE_ROLLS: usize = 4;
+// Brings the low-passed brown noise up to rumble audibly over the rain
+const THUNDER_GAIN: f32 = 4.0;
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct ThunderstormParams {
+    // Average thunder rolls per minute
+    pub thunder_per_minute: f32,
+    // Fixed seed for a reproducible storm; random when unset
+    pub seed: Option<u64>,
+}
+
+impl Default for ThunderstormParams {
+    fn default() -> Self {
+        ThunderstormParams {
+            thunder_per_minute: 2.0,
+            seed: None,
+        }
+    }
+}
+
+// One thunder roll: low-passed noise that swells in, then rumbles on through
+// a long decay
+struct Roll {
+    filter: Biquad,
+    age: u32,
+    attack: u32,
+    level: f32,
+    decay: f32,
+    // Irregular swells within the roll, gliding towards `rumble_target`
+    rumble: f32,
+    rumble_target: f32,
+    samples_until_rumble: u32,
+}
+
+// Rain with distant thunder. Thunder onsets are a Poisson process counted
+// down in whole samples, so each roll starts on an exact sample of the
+// output no matter how the audio is buffered.
+pub struct Thunderstorm {
+    rng: StdRng,
+    rain: Rain,
+    thunder_noise: BrownNoise,
+    rolls: Vec<Roll>,
+    // Average samples between rolls, or None when thunder is off
+    mean_gap: Option<f32>,
+    samples_until_thunder: u64,
+    rolls_started: u64,
+}
+
+impl Thunderstorm {
+    pub fn new(rain: RainParams, params: ThunderstormParams) -> Self {
+        let mut rng = match params.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let rain = RainParams {
+            seed: Some(rng.next_u64()),
+            ..rain
+        };
+        let mean_gap = (params.thunder_per_minute > 0.0)
+            .then(|| 60.0 * SAMPLE_RATE as f32 / params.thunder_per_minute);
+
+        let mut storm = Thunderstorm {
+            rain: Rain::new(rain),
+            thunder_noise: BrownNoise::with_seed(rng.next_u64()),
+            rolls: Vec::with_capacity(MAX_ACTIVE_ROLLS),
+            mean_gap,
+            samples_until_thunder: 0,
+            rolls_started: 0,
+            rng,
+        };
+        storm.schedule_next_thunder();
+        storm
+    }
+
+    // Exponentially distributed gaps, at least one sample long
+    fn schedule_next_thunder(&mut self) {
+        self.samples_until_thunder = match self.mean_gap {
+            Some(mean_gap) => {
+                let u: f32 = self.rng.gen_range(f32::EPSILON..1.0);
+                (-u.ln() * mean_gap) as u64 + 1
+            }
+            None => u64::MAX,
+        };
+    }
+
+    fn spawn_roll(&mut self) {
+        self.rolls_started += 1;
+        if self.rolls.len() >= MAX_ACTIVE_ROLLS {
+            return;
+        }
+
+        // Farther strikes are quieter, duller, slower to arrive and longer to fade
+        let distance: f32 = self.rng.gen_range(0.0..1.0);
+        let sample_rate = SAMPLE_RATE as f32;
+        let cutoff = 250.0 - 170.0 * distance;
+        let attack_seconds = 0.1 + 0.6 * distance + self.rng.gen_range(0.0..0.3);
+        let decay_seconds = 1.5 + 2.5 * distance + self.rng.gen_range(0.0..1.0);
+
+        self.rolls.push(Roll {
+            filter: Biquad::low_pass(cutoff, sample_rate),
+            age: 0,
+            attack: (attack_seconds * sample_rate) as u32,
+            level: 1.0 - 0.6 * distance,
+            decay: (-1.0 / (decay_seconds * sample_rate)).exp(),
+            rumble: 1.0,
+            rumble_target: 1.0,
+            samples_until_rumble: 0,
+        });
+    }
+
+    fn next_thunder(&mut self, noise: f32) -> f32 {
+        let mut thunder = 0.0;
+        for roll in &mut self.rolls {
+            if roll.samples_until_rumble == 0 {
+                roll.rumble_target = self.rng.gen_range(0.3..1.0);
+                roll.samples_until_rumble = self.rng.gen_range(SAMPLE_RATE / 12..SAMPLE_RATE * 2 / 5);
+            }
+            roll.samples_until_rumble -= 1;
+            roll.rumble += (roll.rumble_target - roll.rumble) * 0.001;
+
+            let envelope = if roll.age < roll.attack {
+                roll.age as f32 / roll.attack as f32
+            } else {
+                roll.level *= roll.decay;
+                1.0
+            };
+            roll.age += 1;
+
+            thunder += roll.filter.process(noise) * roll.level * envelope * roll.rumble;
+        }
+        // Forget rolls once they've decayed to inaudible (-80 dB)
+        self.rolls.retain(|roll| roll.level > 1e-4);
+        thunder * THUNDER_GAIN
+    }
+}
+
+impl Iterator for Thunderstorm {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.samples_until_thunder == 0 {
+            self.spawn_roll();
+            self.schedule_next_thunder();
+        }
+        self.samples_until_thunder -= 1;
+
+        let rain = self.rain.next()?;
+        let noise = self.thunder_noise.next()?;
+        Some(rain + self.next_thunder(noise))
+    }
+}
+
+impl Source for Thunderstorm {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thunder_starts_on_the_scheduled_sample() {
+        let params = ThunderstormParams {
+            thunder_per_minute: 30.0,
+            seed: Some(4),
+        };
+        let mut storm = Thunderstorm::new(RainParams::default(), params);
+
+        for _ in 0..3 {
+            let wait = storm.samples_until_thunder as usize;
+            let started = storm.rolls_started;
+            storm.by_ref().take(wait).for_each(drop);
+            assert_eq!(storm.rolls_started, started, "a roll started early");
+            storm.next();
+            assert_eq!(storm.rolls_started, started + 1, "a roll started late");
+        }
+    }
+}
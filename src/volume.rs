@@ -0,0 +1,145 @@
+use serde::Deserialize;
+
+// Quietest audible setting; 1% maps here and 0% is silence
+const MIN_DB: f32 = -40.0;
+
+// Playback volume in whole percent steps.
+//
+// The percentage is mapped onto a decibel scale instead of being used as a
+// linear multiplier, so every step sounds like the same change in loudness:
+// 100% is 0 dB, 50% is -20 dB and 1% is -40 dB.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Volume {
+    percent: u8,
+}
+
+// How volume is shown in the menu and log output
+#[derive(Clone, Copy, PartialEq, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumeScale {
+    #[default]
+    Percent,
+    Db,
+}
+
+impl Volume {
+    pub fn from_percent(percent: i32) -> Self {
+        Volume {
+            percent: percent.clamp(0, 100) as u8,
+        }
+    }
+
+    // Rounds to the nearest whole percent; anything below the quietest step is silence
+    pub fn from_db(db: f32) -> Self {
+        if db < MIN_DB {
+            return Volume::from_percent(0);
+        }
+        let percent = (100.0 * (1.0 - db / MIN_DB)).round() as i32;
+        Volume::from_percent(percent.max(1))
+    }
+
+    pub fn percent(self) -> u8 {
+        self.percent
+    }
+
+    pub fn db(self) -> f32 {
+        if self.percent == 0 {
+            f32::NEG_INFINITY
+        } else {
+            MIN_DB * (1.0 - self.percent as f32 / 100.0)
+        }
+    }
+
+    // Linear gain to hand to `Sink::set_volume`
+    pub fn amplitude(self) -> f32 {
+        if self.percent == 0 {
+            0.0
+        } else {
+            10f32.powf(self.db() / 20.0)
+        }
+    }
+
+    // Gain for a fractional percentage, for fades that glide between steps.
+    // Below the quietest step it ramps linearly down to silence.
+    pub fn amplitude_at(percent: f32) -> f32 {
+        if percent < 1.0 {
+            return percent.max(0.0) * Volume::from_percent(1).amplitude();
+        }
+        10f32.powf(MIN_DB * (1.0 - percent.min(100.0) / 100.0) / 20.0)
+    }
+
+    pub fn step(self, delta: i32) -> Self {
+        Volume::from_percent(self.percent as i32 + delta)
+    }
+
+    pub fn label(self, scale: VolumeScale) -> String {
+        match scale {
+            VolumeScale::Percent => format!("{}%", self.percent),
+            VolumeScale::Db if self.percent == 0 => "Muted".to_string(),
+            VolumeScale::Db => format!("{:.1} dB", self.db()),
+        }
+    }
+}
+
+impl std::str::FromStr for Volume {
+    type Err = String;
+
+    // Accepts "35", "35%" or a decibel value like "-12dB"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let lower = s.to_lowercase();
+
+        if let Some(db) = lower.strip_suffix("db") {
+            let db: f32 = db
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid decibel volume \"{}\"", s))?;
+            if db > 0.0 {
+                return Err(format!("volume {} is above the 0 dB maximum", s));
+            }
+            return Ok(Volume::from_db(db));
+        }
+
+        let percent: i32 = lower
+            .trim_end_matches('%')
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid volume \"{}\", expected 0-100 or a dB value", s))?;
+        if !(0..=100).contains(&percent) {
+            return Err(format!("volume {} is outside 0-100%", s));
+        }
+        Ok(Volume::from_percent(percent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_percent_and_decibels() {
+        assert_eq!("35".parse::<Volume>().unwrap().percent(), 35);
+        assert_eq!(" 35% ".parse::<Volume>().unwrap().percent(), 35);
+        // -12 dB is 30% of the way down the 40 dB range
+        assert_eq!("-12dB".parse::<Volume>().unwrap().percent(), 70);
+        assert_eq!("0 db".parse::<Volume>().unwrap().percent(), 100);
+        for bad in ["+3dB", "101", "-1", "loud", "dB"] {
+            assert!(bad.parse::<Volume>().is_err(), "accepted {}", bad);
+        }
+    }
+
+    #[test]
+    fn decibels_round_trip_through_the_curve() {
+        for percent in 1..=100 {
+            let volume = Volume::from_percent(percent);
+            assert_eq!(Volume::from_db(volume.db()), volume);
+            assert!((20.0 * volume.amplitude().log10() - volume.db()).abs() < 1e-3);
+        }
+        // 0% is silence, 1% the quietest step, and anything quieter is silence
+        assert_eq!(Volume::from_percent(0).amplitude(), 0.0);
+        assert_eq!(Volume::from_percent(1).db(), -39.6);
+        assert_eq!(Volume::from_db(-39.9).percent(), 1);
+        assert_eq!(Volume::from_db(-40.5).percent(), 0);
+        assert_eq!(Volume::from_percent(150).percent(), 100);
+    }
+}
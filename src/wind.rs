@@ -0,0 +1,166 @@
+use crate::eq::Biquad;
+use crate::noise::{PinkNoise, WhiteNoise, SAMPLE_RATE};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use rodio::Source;
+use serde::Deserialize;
+
+// The band-pass filters are retuned this often, in samples, rather than every sample
+const FILTER_UPDATE_INTERVAL: u32 = 32;
+// Time constant of the gust smoothing, in seconds
+const GUST_SMOOTHING: f32 = 1.2;
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct WindParams {
+    // 0.0 is a steady breeze, 1.0 is frequent strong gusts
+    pub gustiness: f32,
+    // Fixed seed for reproducible gusts; random when unset
+    pub seed: Option<u64>,
+}
+
+impl Default for WindParams {
+    fn default() -> Self {
+        WindParams {
+            gustiness: 0.5,
+            seed: None,
+        }
+    }
+}
+
+// Wind from band-pass filtered noise. A smoothed random gust level moves the
+// filter's center frequency and the gain together, so gusts swell and rise in
+// pitch; strong gusts also bring in a narrow, whistling band.
+pub struct Wind {
+    rng: StdRng,
+    gustiness: f32,
+    body_noise: PinkNoise,
+    whistle_noise: WhiteNoise,
+    body_filter: Biquad,
+    whistle_filter: Biquad,
+    // Current gust strength from 0.0 to 1.0, gliding towards `gust_target`
+    gust: f32,
+    gust_target: f32,
+    gust_coeff: f32,
+    samples_until_gust: u32,
+    samples_until_filter_update: u32,
+}
+
+impl Wind {
+    pub fn new(params: WindParams) -> Self {
+        let mut rng = match params.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let sample_rate = SAMPLE_RATE as f32;
+
+        Wind {
+            body_noise: PinkNoise::with_seed(rng.next_u64()),
+            whistle_noise: WhiteNoise::with_seed(rng.next_u64()),
+            body_filter: Biquad::band_pass(400.0, 0.7, sample_rate),
+            whistle_filter: Biquad::band_pass(900.0, 6.0, sample_rate),
+            gustiness: params.gustiness.clamp(0.0, 1.0),
+            gust: 0.3,
+            gust_target: 0.3,
+            gust_coeff: 1.0 - (-1.0 / (GUST_SMOOTHING * sample_rate)).exp(),
+            samples_until_gust: 0,
+            samples_until_filter_update: 0,
+            rng,
+        }
+    }
+
+    // Picks the next gust level and how long until the one after that
+    fn next_gust(&mut self) {
+        // Gusty wind changes more often and swings further
+        let max_wait = 6.0 - 4.0 * self.gustiness;
+        let wait = self.rng.gen_range(0.5..max_wait);
+        let calm = 0.3;
+        let swing = self.rng.gen_range(-0.3..0.7) * self.gustiness;
+        self.gust_target = (calm + swing).clamp(0.0, 1.0);
+        self.samples_until_gust = (wait * SAMPLE_RATE as f32) as u32;
+    }
+}
+
+impl Iterator for Wind {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.samples_until_gust == 0 {
+            self.next_gust();
+        }
+        self.samples_until_gust -= 1;
+        self.gust += (self.gust_target - self.gust) * self.gust_coeff;
+
+        if self.samples_until_filter_update == 0 {
+            let sample_rate = SAMPLE_RATE as f32;
+            self.body_filter
+                .retune(&Biquad::band_pass(250.0 + 650.0 * self.gust, 0.7, sample_rate));
+            self.whistle_filter
+                .retune(&Biquad::band_pass(600.0 + 900.0 * self.gust, 6.0, sample_rate));
+            self.samples_until_filter_update = FILTER_UPDATE_INTERVAL;
+        }
+        self.samples_until_filter_update -= 1;
+
+        let body = self.body_filter.process(self.body_noise.next()?) * (0.3 + 0.7 * self.gust);
+        let whistle = self.whistle_filter.process(self.whistle_noise.next()?) * 0.25 * self.gust * self.gust;
+
+        Some(body + whistle)
+    }
+}
+
+impl Source for Wind {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gusts_make_it_louder() {
+        // Level and gust strength over each tenth of a second for a minute
+        let mut wind = Wind::new(WindParams { gustiness: 1.0, seed: Some(4) });
+        let mut windows: Vec<(f32, f32)> = (0..600)
+            .map(|_| {
+                let samples: Vec<f32> = wind.by_ref().take(SAMPLE_RATE as usize / 10).collect();
+                let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+                (wind.gust, rms)
+            })
+            .collect();
+        windows.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mean = |windows: &[(f32, f32)]| windows.iter().map(|(_, rms)| rms).sum::<f32>() / windows.len() as f32;
+        let (calm, gusty) = (mean(&windows[..200]), mean(&windows[400..]));
+        assert!(gusty > 1.5 * calm, "{} {}", calm, gusty);
+    }
+
+    #[test]
+    fn gustiness_sets_how_much_the_level_moves() {
+        // Range of the gust level over half a minute
+        let swing = |gustiness: f32| {
+            let mut wind = Wind::new(WindParams { gustiness, seed: Some(2) });
+            let (mut low, mut high) = (f32::MAX, f32::MIN);
+            for _ in 0..48000 * 30 {
+                wind.next();
+                low = low.min(wind.gust);
+                high = high.max(wind.gust);
+            }
+            high - low
+        };
+        assert!(swing(0.0) < 1e-3);
+        assert!(swing(1.0) > 0.3);
+    }
+}